@@ -0,0 +1,188 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+
+/// `set_mempolicy(2)` mode restricting allocations to the given nodes. Not exported by `libc`.
+const MPOL_BIND: libc::c_int = 2;
+
+/// A list of CPU or NUMA node ids in the kernel's list format, e.g. `0-3,8,10-11`. Order is
+/// preserved so that threads are assigned ids in the order written.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdList(Vec<usize>);
+impl IdList {
+    pub fn ids(&self) -> &[usize] { &self.0 }
+
+    /// The id assigned to thread `index`; threads wrap around the list.
+    pub fn for_thread(&self, index: usize) -> usize { self.0[index % self.0.len()] }
+}
+impl FromStr for IdList {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<IdList> {
+        let invalid = || anyhow!("invalid id list '{s}'; expected a form such as '0-3,8,10-11'");
+        let mut ids: Vec<usize> = Vec::new();
+        for part in s.trim().split(',').filter(|p| !p.is_empty()) {
+            match part.split_once('-') {
+                Some((low, high)) => {
+                    let low: usize = low.trim().parse().map_err(|_| invalid())?;
+                    let high: usize = high.trim().parse().map_err(|_| invalid())?;
+                    if high < low { return Err(invalid()); }
+                    ids.extend(low..=high);
+                },
+                None => ids.push(part.trim().parse().map_err(|_| invalid())?),
+            }
+        }
+        if ids.is_empty() { return Err(invalid()); }
+        Ok(IdList(ids))
+    }
+}
+impl fmt::Display for IdList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ids: Vec<String> = self.0.iter().map(|id| id.to_string()).collect();
+        write!(f, "{}", ids.join(","))
+    }
+}
+
+/// Where a thread ended up after `Placement::apply`. `None` means the thread may run on, or
+/// allocate from, any CPU or node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Location {
+    pub cpu: Option<usize>,
+    pub node: Option<usize>,
+}
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |id: Option<usize>| id.map_or("*".to_string(), |id| id.to_string());
+        write!(f, "[cpu:{}][node:{}]", show(self.cpu), show(self.node))
+    }
+}
+
+/// # Overview
+/// How worker threads, and the memory they allocate, are placed on the machine. Threads are
+/// assigned ids from a list in order, wrapping around if there are more threads than ids.
+///
+/// When a thread is pinned, its subsequent allocations are also bound to the NUMA node it was
+/// pinned to. Buffers should therefore be allocated _after_ `apply` so that they are local to
+/// the thread which uses them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Placement {
+    /// Threads float and memory follows the default policy.
+    #[default]
+    None,
+    /// Each thread is pinned to a single CPU.
+    Cpus(IdList),
+    /// Each thread is pinned to all CPUs of a single NUMA node.
+    Nodes(IdList),
+}
+impl Placement {
+    /// Checks that every CPU or node named by the placement exists on this machine.
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Placement::None => Ok(()),
+            Placement::Cpus(cpus) => cpus.ids().iter().try_for_each(|cpu| {
+                if PathBuf::from(format!("/sys/devices/system/cpu/cpu{cpu}")).exists() { Ok(()) }
+                else { Err(anyhow!("cpu {cpu} does not exist on this machine")) }
+            }),
+            Placement::Nodes(nodes) => nodes.ids().iter().try_for_each(|node| {
+                cpus_of_node(*node).map(|_| ())
+            }),
+        }
+    }
+
+    /// Places the calling thread, which is logical thread `index`, and returns where it was
+    /// placed.
+    pub fn apply(&self, index: usize) -> Result<Location> {
+        match self {
+            Placement::None => Ok(Location::default()),
+            Placement::Cpus(cpus) => {
+                let cpu: usize = cpus.for_thread(index);
+                set_affinity(&[cpu])?;
+                let node: Option<usize> = node_of_cpu(cpu);
+                if let Some(node) = node { bind_memory(node)?; }
+                Ok(Location { cpu: Some(cpu), node })
+            },
+            Placement::Nodes(nodes) => {
+                let node: usize = nodes.for_thread(index);
+                set_affinity(cpus_of_node(node)?.ids())?;
+                bind_memory(node)?;
+                Ok(Location { cpu: None, node: Some(node) })
+            },
+        }
+    }
+}
+
+/// Restricts the calling thread to `cpus`.
+fn set_affinity(cpus: &[usize]) -> Result<()> {
+    // SAFETY: cpu_set_t is a plain bitmask for which all zeroes is the empty set, and
+    // sched_setaffinity only reads `size_of::<cpu_set_t>()` bytes from the pointer.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in cpus {
+            if *cpu >= libc::CPU_SETSIZE as usize {
+                return Err(anyhow!("cpu {cpu} exceeds the largest supported id {}", libc::CPU_SETSIZE - 1));
+            }
+            libc::CPU_SET(*cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(anyhow!("unable to pin thread to cpus {cpus:?}: {}", std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+/// Restricts future allocations of the calling thread to `node`.
+fn bind_memory(node: usize) -> Result<()> {
+    let mut mask: Vec<libc::c_ulong> = vec![0; node / libc::c_ulong::BITS as usize + 1];
+    mask[node / libc::c_ulong::BITS as usize] |= 1 << (node % libc::c_ulong::BITS as usize);
+
+    // The kernel ignores the last bit of `maxnode`, hence the extra one.
+    let maxnode: libc::c_ulong = (mask.len() * libc::c_ulong::BITS as usize + 1) as libc::c_ulong;
+
+    // SAFETY: the mask outlives the call and holds at least `maxnode - 1` bits.
+    let ret: libc::c_long = unsafe {
+        libc::syscall(libc::SYS_set_mempolicy, MPOL_BIND, mask.as_ptr(), maxnode)
+    };
+    if ret != 0 {
+        return Err(anyhow!("unable to bind memory to node {node}: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// The NUMA node which `cpu` belongs to, or `None` if the machine doesn't report one.
+pub fn node_of_cpu(cpu: usize) -> Option<usize> {
+    std::fs::read_dir(PathBuf::from(format!("/sys/devices/system/cpu/cpu{cpu}")))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find_map(|entry| entry.file_name().to_str()?.strip_prefix("node")?.parse().ok())
+}
+
+/// The CPUs belonging to NUMA node `node`.
+pub fn cpus_of_node(node: usize) -> Result<IdList> {
+    let path: PathBuf = PathBuf::from(format!("/sys/devices/system/node/node{node}/cpulist"));
+    std::fs::read_to_string(&path)
+        .map_err(|e| anyhow!("unable to read cpus of node {node} from {}: {e}", path.display()))?
+        .parse()
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn id_list_parsing() {
+        let list: IdList = "0-3,8,10-11".parse().unwrap();
+        assert_eq!(list.ids(), &[0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(list.for_thread(7), 0);
+        assert_eq!(list.to_string(), "0,1,2,3,8,10,11");
+
+        assert!("3-1".parse::<IdList>().is_err());
+        assert!("".parse::<IdList>().is_err());
+        assert!("zero".parse::<IdList>().is_err());
+    }
+}
@@ -37,6 +37,13 @@ impl<const P: usize, const W: usize, const B: usize> Chapter<P,W,B> {
         Chapter(PageOrBytes::<P,W,B> { bytes: [0; B] })
     }
 
+    /// Creates a new, zeroed, Chapter directly on the heap. Chapters are usually too large to be
+    /// built on a worker thread's stack and then moved into a `Box`.
+    pub fn new_boxed() -> Box<Chapter<P,W,B>> {
+        // SAFETY: Both variants of the union are plain integers for which zero is valid.
+        unsafe { Box::<Chapter<P,W,B>>::new_zeroed().assume_init() }
+    }
+
     pub fn zeroize(&mut self) {
         unsafe { let _ = &self.0.bytes.fill_with(Default::default); }
     }
@@ -1,29 +1,150 @@
 //use std::os::unix::prelude::OpenOptionsExt;
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::{
         File, OpenOptions
-    }, path::PathBuf,
-    os::unix::fs::OpenOptionsExt
+    }, path::{Path, PathBuf},
+    io::ErrorKind,
+    os::{fd::AsRawFd, unix::fs::{FileExt, MetadataExt, OpenOptionsExt}},
+    str::FromStr,
 };
 use std::fmt;
 use serde::{Deserialize, Serialize};
 use anyhow::{anyhow, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use super::{page::Page, placement::FilePlacement, sizes::FileSizes, DATA_WORDS, PAGES_PER_CHAPTER, PAGE_BYTES};
     
 
 //use log::trace;
 //use log::debug;
 
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FileOptions {
     pub directo_io: bool,
+    /// How the blocks of each file are allocated when it is created.
+    #[serde(default)]
+    pub preallocation: Preallocation,
+    /// Which directory, and so root, each file is placed in.
+    #[serde(default)]
+    pub placement: FilePlacement,
+}
+
+/// # Overview
+/// How a file's blocks are allocated when a constellation creates it. Allocation can dominate the
+/// cost of the first write to a file on some file systems, so it is chosen explicitly.
+///
+/// - `Sparse`: the file is only given its length; blocks are allocated by the first write.
+/// - `Allocate`: blocks are reserved with `fallocate` and read back as zeroes.
+/// - `AllocateKeepSize`: as `Allocate` but the file's length remains zero until written. Reads
+///   before the file is written are therefore short.
+/// - `Zero`: every byte is written with zero.
+/// - `Pages`: every page is written with valid `Page` data for the seed, as a create phase would.
+/// - `PunchHole`: blocks are reserved and then deallocated with `FALLOC_FL_PUNCH_HOLE`, leaving a
+///   file which has been allocated once but holds no blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Preallocation {
+    #[default]
+    Sparse,
+    Allocate,
+    AllocateKeepSize,
+    Zero,
+    /// Seed of the page data.
+    Pages(u64),
+    PunchHole,
+}
+impl Preallocation {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Preallocation::Sparse           => "sparse",
+            Preallocation::Allocate         => "fallocate",
+            Preallocation::AllocateKeepSize => "fallocate-keep-size",
+            Preallocation::Zero             => "zero",
+            Preallocation::Pages(_)         => "pages",
+            Preallocation::PunchHole        => "punch-hole",
+        }
+    }
+
+    /// Returns a copy which fills pages using `seed`. Other strategies are unchanged.
+    pub fn with_seed(self, seed: u64) -> Preallocation {
+        match self {
+            Preallocation::Pages(_) => Preallocation::Pages(seed),
+            other => other,
+        }
+    }
+
+    /// Allocates `size` bytes of `file`, which is file `id` of its constellation, from its start.
+    fn apply(&self, file: &File, id: u64, size: u64) -> Result<()> {
+        match self {
+            Preallocation::Sparse => file.set_len(size)?,
+            Preallocation::Allocate => fallocate(file, 0, 0, size)?,
+            Preallocation::AllocateKeepSize => fallocate(file, libc::FALLOC_FL_KEEP_SIZE, 0, size)?,
+            Preallocation::Zero => {
+                let zeroes: Vec<u8> = vec![0; FILL_BYTES.min(size as usize)];
+                (0..size).step_by(FILL_BYTES).try_for_each(|offset| {
+                    file.write_all_at(&zeroes[..zeroes.len().min((size - offset) as usize)], offset)
+                })?;
+            },
+            Preallocation::Pages(seed) => {
+                let mut buffer: Vec<u8> = Vec::with_capacity(FILL_BYTES);
+                (0..size).step_by(FILL_BYTES).try_for_each(|offset| {
+                    buffer.clear();
+                    let end: u64 = size.min(offset + FILL_BYTES as u64);
+                    (offset / PAGE_BYTES as u64..end.div_ceil(PAGE_BYTES as u64)).for_each(|page| {
+                        buffer.extend_from_slice(bytemuck::bytes_of(&Page::<DATA_WORDS>::new(*seed, id, page)));
+                    });
+                    buffer.truncate((end - offset) as usize);
+                    file.write_all_at(&buffer, offset)
+                })?;
+            },
+            Preallocation::PunchHole => {
+                fallocate(file, 0, 0, size)?;
+                fallocate(file, libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, 0, size)?;
+            },
+        }
+        Ok(())
+    }
+}
+impl FromStr for Preallocation {
+    type Err = anyhow::Error;
+
+    /// Parses a strategy by name. `pages` uses seed zero; see `with_seed`.
+    fn from_str(s: &str) -> Result<Preallocation> {
+        [Preallocation::Sparse, Preallocation::Allocate, Preallocation::AllocateKeepSize,
+         Preallocation::Zero, Preallocation::Pages(0), Preallocation::PunchHole]
+            .into_iter()
+            .find(|p| p.to_str() == s.trim())
+            .ok_or_else(|| anyhow!("invalid preallocation '{s}'; expected one of sparse, fallocate, fallocate-keep-size, zero, pages or punch-hole"))
+    }
+}
+impl fmt::Display for Preallocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// Bytes written per system call when filling a file during preallocation.
+const FILL_BYTES: usize = PAGE_BYTES * PAGES_PER_CHAPTER;
+
+/// `fallocate(2)` over `length` bytes of `file` from `offset`.
+fn fallocate(file: &File, mode: libc::c_int, offset: u64, length: u64) -> Result<()> {
+    if length == 0 { return Ok(()); }
+    // SAFETY: the descriptor is owned by `file`, which outlives the call.
+    let ret: libc::c_int = unsafe {
+        libc::fallocate(file.as_raw_fd(), mode, offset as libc::off_t, length as libc::off_t)
+    };
+    if ret != 0 {
+        return Err(anyhow!("fallocate failed: {}", std::io::Error::last_os_error()));
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
 struct FileIdentifier {
     file: u64,
+    /// The innermost directory containing the file, which determines its root.
     directory: u64,
-    root: u64
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,7 +154,7 @@ struct LayerInfo {
     /// Total number of items in this layer
     count: u64,
     /// The number of bytes in items of this layer (only relevant for files)
-    size: Option<u64>,
+    size: Option<FileSizes>,
     /// Additional options for this layer (only relevant for files)
     options: Option<FileOptions>,
 }
@@ -54,6 +175,9 @@ struct LayerInfo {
 ///
 /// Directories and Files will have the naming scheme: <prefix><ID>.
 ///
+/// The placement described below is the default, round-robin, `FilePlacement`. Other policies
+/// choose a different directory for each file but keep the directory structure and names.
+///
 /// Directories and files will be created, and numbered (ID), in a round-robin fashion. That is
 /// the first file will reside in the first directory, the second file in the second directory, and
 /// so on. If their are more files than directories, they will loop back (as in modular
@@ -87,13 +211,42 @@ struct LayerInfo {
 /// Note that under the `root`s  directories and files that differ with respect to their peers only
 /// in the number. Each is composed of a `prefix` and a `number` such that we get
 /// `<prefix><number>` for each. 
+///
+/// # Nested Directories
+///
+/// Directories may be nested in any number of layers, each with its own prefix and fan-out: the
+/// number of directories it holds within each directory of the layer above (or each root). Files
+/// only reside in the innermost layer. Placement is a mixed-radix generalization of the above;
+/// a directory of a layer is numbered `n % m`, where `n` is the number of any file beneath it and
+/// `m` is the total number of directories in that layer. With two roots and layers `a` and `b`,
+/// each of fan-out two:
+///
+/// ```txt
+/// |_ /sda   (root 0)
+/// |  |- /a0
+/// |  |  |- /b0        // 0 = FileId % 8
+/// |  |  |  |- /file00
+/// |  |  |  `- /file08
+/// |  |  `- /b4        // 4 = FileId % 8
+/// |  `- /a2
+/// |     |- /b2
+/// |     `- /b6
+/// `- /sdb   (root 1)
+///    |- /a1
+///    |  |- /b1
+///    |  `- /b5
+///    `- /a3
+///       |- /b3
+///       `- /b7
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileConstellation {
     /// Root path(s) for the directories
     roots: Vec<PathBuf>,
 
-    /// Info about The directories under the root
-    directories: LayerInfo,
+    /// Info about the layers of directories under the roots, outermost first. The count of each
+    /// layer is its fan-out.
+    directories: Vec<LayerInfo>,
 
     /// Info for files contained in a single directory
     files: LayerInfo,
@@ -107,11 +260,46 @@ impl FileConstellation {
     pub fn new(roots: Vec<PathBuf>, 
                directories_per_root: (String, u64),
                files_per_directory: (String, u64), 
-               size_of_files: u64, 
+               size_of_files: FileSizes, 
                options: FileOptions,
                drop: bool) -> Result<FileConstellation> {
 
+        if directories_per_root.1 == 0 || (files_per_directory.1 / directories_per_root.1) < 1 {
+            return Err(anyhow!("attempted to create more directories ({}) than files ({})", 
+                    directories_per_root.1, files_per_directory.1));
+        }
+
+        FileConstellation::nested(roots, vec![directories_per_root], files_per_directory, size_of_files, options, drop)
+    }
+
+    /// Creates a constellation with the given layers of directories, outermost first, as
+    /// `(prefix, fan-out)` pairs. See "Nested Directories" above.
+    pub fn nested(roots: Vec<PathBuf>, 
+                  directory_layers: Vec<(String, u64)>,
+                  files_per_directory: (String, u64), 
+                  size_of_files: FileSizes, 
+                  options: FileOptions,
+                  drop: bool) -> Result<FileConstellation> {
+        let mut file_system_structure: FileConstellation = FileConstellation::describe(
+            roots, directory_layers, files_per_directory, size_of_files, options
+        )?;
+        file_system_structure.drop = drop;
+
+        FileConstellation::instantiate(file_system_structure)
+    }
+
+    /// Describes a constellation, as `nested` would create it, without creating anything. Used to
+    /// refer to a constellation which already exists; it is never removed on drop.
+    pub fn describe(roots: Vec<PathBuf>, 
+                    directory_layers: Vec<(String, u64)>,
+                    files_per_directory: (String, u64), 
+                    size_of_files: FileSizes, 
+                    options: FileOptions) -> Result<FileConstellation> {
+
         // Validation of parameters
+        if roots.is_empty() {
+            return Err(anyhow!("a constellation requires at least one root"));
+        }
         roots.iter().try_for_each(|root| {
             if root.exists() { Ok(()) } 
             else {
@@ -119,41 +307,56 @@ impl FileConstellation {
             }
         })?;
 
-        if directories_per_root.1 == 0 || (files_per_directory.1 / directories_per_root.1) < 1 {
-            return Err(anyhow!("attempted to create more directories ({}) than files ({})", 
-                    directories_per_root.1, files_per_directory.1));
+        if directory_layers.is_empty() || directory_layers.iter().any(|(_, fan_out)| *fan_out == 0) {
+            return Err(anyhow!("a constellation requires at least one layer of directories and each must have a non-zero fan-out"));
         }
 
-        if size_of_files == 0  {
-            return Err(anyhow!("invalid file size; file size must be non-zero"));
+        if files_per_directory.1 == 0 {
+            return Err(anyhow!("a constellation requires at least one file per directory"));
         }
 
-        // NOTE: Is this really a constraint?
-        if size_of_files % 64 != 0  {
-            return Err(anyhow!("invalid file byte alignment; file size must be a multiple of 64"));
+        // Names are a prefix followed by an index, so a prefix must not leave its directory.
+        if let Some(prefix) = directory_layers.iter().map(|(prefix, _)| prefix).chain([&files_per_directory.0])
+                                              .find(|prefix| prefix.contains(std::path::MAIN_SEPARATOR) || prefix.contains('\0')) {
+            return Err(anyhow!("invalid name prefix '{prefix}'; prefixes must not contain path separators"));
         }
 
+        options.placement.validate(roots.len() as u64)?;
+
         // Set up configuration
-        let file_system_structure: FileConstellation =  FileConstellation {
+        let constellation: FileConstellation = FileConstellation::layout(roots, directory_layers, files_per_directory, size_of_files, options, false);
+
+        // Byte totals and offsets across the constellation must fit in a u64.
+        let (count, largest): (u64, u64) = (constellation.count(), constellation.sizes().largest());
+        if count.checked_mul(largest).is_none() {
+            return Err(anyhow!("{count} files of up to {} may hold more bytes than can be counted; lower the largest file size", indicatif::HumanBytes(largest)));
+        }
+        Ok(constellation)
+    }
+
+    /// The structure of a constellation, without validating or creating anything on disk.
+    fn layout(roots: Vec<PathBuf>, 
+              directory_layers: Vec<(String, u64)>,
+              files_per_directory: (String, u64), 
+              size_of_files: FileSizes, 
+              options: FileOptions,
+              drop: bool) -> FileConstellation {
+        FileConstellation {
                 roots,
-                directories: LayerInfo { prefix: directories_per_root.0,
-                                         count: directories_per_root.1,
-                                         size: None,
-                                         options: None
-                },
+                directories: directory_layers.into_iter()
+                                             .map(|(prefix, count)| LayerInfo { prefix,
+                                                                                count,
+                                                                                size: None,
+                                                                                options: None
+                                             })
+                                             .collect(),
                 files:       LayerInfo { prefix: files_per_directory.0,
                                          count: files_per_directory.1,
                                          size: Some(size_of_files),
                                          options: Some(options)
                 },
                 drop,
-        };
-        
-        FileConstellation::instantiate(file_system_structure)
-    }
-
-    pub fn from_configuration(_file: &PathBuf) -> Result<FileConstellation> {
-        unimplemented!("future feature");
+        }
     }
 
     /// Open a file with identifier `id` for (read|write). The `id` is the absolute identification
@@ -163,78 +366,263 @@ impl FileConstellation {
         self.open_with_checked_id(self.file_identifier(id)?, read, write)
     }
 
+    /// Deallocates `length` bytes of file `id` from `offset` with `FALLOC_FL_PUNCH_HOLE`. The file's
+    /// length is unchanged and the range reads back as zeroes.
+    pub fn punch_hole(&self, id: u64, offset: u64, length: u64) -> Result<()> {
+        let file: File = self.open(id, false, true)?;
+        fallocate(&file, libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE, offset, length)
+    }
+
+    /// Creates the directories and files of a described constellation. See `describe`.
+    pub fn create(self) -> Result<FileConstellation> {
+        FileConstellation::instantiate(self)
+    }
+
+    /// Flushes the data of every file to stable storage.
+    pub fn sync(&self) -> Result<()> {
+        (0..self.count()).into_par_iter()
+                         .try_for_each(|id| Ok(self.open(id, true, false)?.sync_all()?))
+    }
+
+    /// Evicts the files of the constellation from the page cache so that they are next read from
+    /// storage. Unlike `/proc/sys/vm/drop_caches` this needs no privileges and leaves other files
+    /// cached. Files are synced first since dirty pages can't be evicted.
+    pub fn drop_cache(&self) -> Result<()> {
+        (0..self.count()).into_par_iter()
+                         .try_for_each(|id| {
+                             let file: File = self.open(id, true, false)?;
+                             file.sync_data()?;
+                             // SAFETY: advice only; the descriptor is valid for the duration of the call.
+                             match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+                                 0 => Ok(()),
+                                 errno => Err(anyhow!("unable to drop file {id} from the page cache: {}", std::io::Error::from_raw_os_error(errno))),
+                             }
+                         })
+    }
+
+    /// Removes every file and directory of the constellation, whether or not it owns them, and
+    /// reports any which could not be removed. Items which don't exist are skipped.
+    pub fn remove(mut self) -> Result<()> {
+        self.drop = false;
+        self.destroy()
+    }
+
+    /// Checks which of the constellation's files exist, and with the expected length, without
+    /// opening them.
+    pub fn survey(&self) -> Result<Survey> {
+        let found: Vec<(u64, Option<std::fs::Metadata>)> = (0..self.count())
+            .into_par_iter()
+            .map(|id| match std::fs::metadata(self.path(id)?) {
+                Ok(metadata) => Ok((id, Some(metadata))),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok((id, None)),
+                Err(e) => Err(anyhow::Error::from(e).context(format!("unable to stat file {id}"))),
+            })
+            .collect::<Result<Vec<(u64, Option<std::fs::Metadata>)>>>()?;
+
+        let mut survey: Survey = Survey::default();
+        found.into_iter().for_each(|(id, metadata)| match metadata {
+            None => survey.missing.push(id),
+            Some(metadata) => {
+                if metadata.len() == self.size(id) { survey.complete += 1; } else { survey.resized.push(id); }
+                survey.allocated += metadata.blocks() * 512;
+            },
+        });
+        Ok(survey)
+    }
+
     /// Toggles whether Drop removes files and directories from the file system.
     pub fn toggle_drop(&mut self) { self.drop = !self.drop; }
 
     /// Returns the dimensions of the Constellation:
-    ///     (roots, directories (per root), files (per directory))
+    ///     (roots, innermost directories (per root), files (per directory))
     #[inline(always)]
     pub fn dimensions(&self) -> (u64, u64, u64) { 
-        (self.roots.len() as u64, self.directories.count, self.files.count)
+        (self.roots.len() as u64, self.directory_count() / self.roots.len() as u64, self.files.count)
     }
     
     /// Returns the total number of files contained in the constellation. Convenience function,
     /// this is the same as the product of the return values from `dimension()`.
     #[inline(always)]
     pub fn count(&self) -> u64 {
-        self.roots.len() as u64 * self.directories.count * self.files.count 
+        self.directory_count() * self.files.count 
     }
 
-    /// Returns the size of a file(s), in bytes, contained in the constellation
+    /// Returns the number of layers of directories.
+    #[inline(always)]
+    pub fn depth(&self) -> usize { self.directories.len() }
+
+    /// Returns the size, in bytes, of file `id`.
+    #[inline(always)]
+    pub fn size(&self, id: u64) -> u64 { self.sizes().size_of(id) }
+
+    /// Returns the distribution of file sizes in the constellation.
     #[inline(always)]
-    pub fn size(&self) -> u64 { self.files.size.expect("files should always be declared with a size") }
+    pub fn sizes(&self) -> &FileSizes { self.files.size.as_ref().expect("files should always be declared with a size") }
+
+    /// Returns the total number of directories, across all roots, which contain files. These are
+    /// the directories of the innermost layer.
+    #[inline(always)]
+    pub fn directory_count(&self) -> u64 { self.layer_count(self.depth() - 1) }
+
+    /// Returns the path of the innermost directory with absolute identifier `id`.
+    pub fn directory(&self, id: u64) -> Result<PathBuf> {
+        if id >= self.directory_count() {
+            return Err(anyhow!("Requested directory id is out of bounds: requested {} >= {} max", id, self.directory_count()));
+        }
+        Ok(self.directory_path(self.depth() - 1, id))
+    }
+
+    /// Returns the policy placing files in directories.
+    #[inline(always)]
+    pub fn placement(&self) -> &FilePlacement {
+        static ROUND_ROBIN: FilePlacement = FilePlacement::RoundRobin;
+        self.files.options.as_ref().map_or(&ROUND_ROBIN, |o| &o.placement)
+    }
+
+    /// Total number of directories, across all roots, in layer `layer`.
+    #[inline(always)]
+    fn layer_count(&self, layer: usize) -> u64 {
+        self.directories[..=layer].iter().fold(self.roots.len() as u64, |count, l| count * l.count)
+    }
+
+    /// Path of directory `id` of layer `layer`. Its ancestor in each outer layer is the one whose
+    /// number is congruent to `id`.
+    fn directory_path(&self, layer: usize, id: u64) -> PathBuf {
+        let mut path: PathBuf = self.roots[(id % self.roots.len() as u64) as usize].clone();
+        self.directories[..=layer].iter().enumerate().for_each(|(l, info)| {
+            let count: u64 = self.layer_count(l);
+            let width: usize = (count.ilog10() + 1) as usize;
+            path.push(format!("{}{:0width$}", info.prefix, id % count));
+        });
+        path
+    }
+
+    /// Returns the path of file `id`.
+    pub fn path(&self, id: u64) -> Result<PathBuf> {
+        self.construct_path(self.file_identifier(id)?)
+    }
+
+    /// Returns the id of the file at `path`; the inverse of `path`. The path must name a file of
+    /// this constellation, though the file need not exist. Paths are compared as given and then,
+    /// if both exist, once made canonical, so relative roots and symbolic links are resolved.
+    pub fn identify(&self, path: &Path) -> Result<u64> {
+        let not_ours = || anyhow!("{} is not a file of this constellation", path.display());
+        let id: u64 = path.file_name()
+                          .and_then(|name| name.to_str())
+                          .and_then(|name| name.strip_prefix(self.files.prefix.as_str()))
+                          .filter(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()))
+                          .and_then(|number| number.parse().ok())
+                          .ok_or_else(not_ours)?;
+        if id >= self.count() { return Err(not_ours()); }
 
+        let expected: PathBuf = self.path(id)?;
+        let canonical = |p: &Path| std::fs::canonicalize(p).ok();
+        if expected == path || canonical(&expected).is_some_and(|e| Some(e) == canonical(path)) {
+            Ok(id)
+        } else {
+            Err(not_ours())
+        }
+    }
+
+    /// Resolves byte `offset` of the file at `path` into the file, chapter and page it belongs to.
+    pub fn address(&self, path: &Path, offset: u64) -> Result<PageAddress> {
+        let file: u64 = self.identify(path)?;
+        if offset >= self.size(file) {
+            return Err(anyhow!("offset {offset} is beyond the end of {}, which holds {} bytes", path.display(), self.size(file)));
+        }
+        let page: u64 = offset / PAGE_BYTES as u64;
+        Ok(PageAddress { file, page, chapter: page / PAGES_PER_CHAPTER as u64, offset: offset % PAGE_BYTES as u64 })
+    }
+
+    /// Maps the global id of a file onto its innermost directory. Every id below `count()` maps to
+    /// a distinct path since file names carry the id.
     #[inline(always)]
     fn file_identifier(&self, id: u64) -> Result<FileIdentifier> {
         let (roots, directories, files): (u64, u64, u64) = self.dimensions();
+        let count: u64 = roots * directories * files;
 
-        if (roots * directories * files) < id {
-            Err(anyhow!("Requested file id is out of bounds: requested {} > {} max", id, self.files.count))
+        if id >= count {
+            Err(anyhow!("Requested file id is out of bounds: requested {} >= {} files", id, count))
         } else {
-            let file: u64      = id % (files * directories * roots);
-            let directory: u64 = id % (        directories * roots);
-            let root: u64      = id % (                      roots);
+            let directory: u64 = self.placement().directory_of(id, count, roots, directories);
 
-            Ok(FileIdentifier { file, directory, root })
+            Ok(FileIdentifier { file: id, directory })
         }
     }
 
+    /// Creates every directory and file across the current rayon thread pool. Directories and
+    /// files which already exist are reused, so an interrupted instantiation may be resumed by
+    /// instantiating the same constellation again.
+    ///
+    /// On failure the constellation is dropped which, if it owns its files, rolls back by removing
+    /// everything it created.
     fn instantiate(fss: FileConstellation) -> Result<FileConstellation> {
-        let (roots, directories, files): (u64, u64, u64) = fss.dimensions();
-        let total_directories: u64 = roots * directories;
-        let total_files: u64 = total_directories * files;
+        let total_directories: u64 = (0..fss.depth()).map(|l| fss.layer_count(l)).sum();
+        let total_files: u64 = fss.count();
 
-        // Create all directories
-        (0..total_directories)
-            .into_iter()
-            .try_for_each(|d|{ fss.create_directory(fss.file_identifier(d)?) })?;
+        // Create all directories, a layer at a time so that parents exist before their children.
+        let progress: ProgressBar = progress_bar(total_directories, "creating directories");
+        (0..fss.depth()).try_for_each(|layer| {
+            (0..fss.layer_count(layer))
+                .into_par_iter()
+                .try_for_each(|d|{ 
+                    fss.create_directory(fss.directory_path(layer, d))?;
+                    progress.inc(1);
+                    Ok::<(), anyhow::Error>(())
+                })
+        })?;
+        progress.finish_and_clear();
 
         // Create all files
+        let progress: ProgressBar = progress_bar(total_files, "creating files");
         (0..total_files)
-            .into_iter()
-            .try_for_each(|f|{ fss.create_file(fss.file_identifier(f)?) })?;
+            .into_par_iter()
+            .try_for_each(|f|{ 
+                fss.create_file(fss.file_identifier(f)?)?;
+                progress.inc(1);
+                Ok::<(), anyhow::Error>(())
+            })?;
+        progress.finish_and_clear();
         Ok(fss)
     }
 
+    /// Removes every file and directory across the current rayon thread pool. Items which are
+    /// already gone are skipped. Every item is attempted even if some can't be removed; the
+    /// first failure is reported along with how many there were.
     fn destroy(&self) -> Result<(), anyhow::Error> {
-        let (roots, directories, files): (u64, u64, u64) = self.dimensions();
-        let total_directories: u64 = roots * directories;
-        let total_files: u64 = total_directories * files;
+        let total_directories: u64 = (0..self.depth()).map(|l| self.layer_count(l)).sum();
+        let total_files: u64 = self.count();
 
         // Remove all files
-        (0..total_files)
-             .into_iter()
-             .try_for_each(|f|{
-                 self.remove_file(self.file_identifier(f)?)
-             })?;
+        let progress: ProgressBar = progress_bar(total_files, "removing files");
+        let mut failures: Vec<anyhow::Error> = (0..total_files)
+             .into_par_iter()
+             .filter_map(|f|{
+                 let removed: Result<()> = self.file_identifier(f).and_then(|id| self.remove_file(id));
+                 progress.inc(1);
+                 removed.err()
+             })
+             .collect();
+        progress.finish_and_clear();
 
-        // Remove all directories
-        (0..total_directories)
-            .into_iter()
-            .try_for_each(|d|{
-                self.remove_directory(self.file_identifier(d)?)
-            })?;
-        Ok(())
+        // Remove all directories, innermost layer first.
+        let progress: ProgressBar = progress_bar(total_directories, "removing directories");
+        (0..self.depth()).rev().for_each(|layer| {
+            failures.extend((0..self.layer_count(layer))
+                .into_par_iter()
+                .filter_map(|d|{
+                    let removed: Result<()> = self.remove_directory(self.directory_path(layer, d));
+                    progress.inc(1);
+                    removed.err()
+                })
+                .collect::<Vec<anyhow::Error>>());
+        });
+        progress.finish_and_clear();
+
+        match failures.len() {
+            0 => Ok(()),
+            n => Err(failures.swap_remove(0).context(format!("unable to remove {n} item(s) of the constellation"))),
+        }
     }
 
     #[inline(always)]
@@ -258,15 +646,9 @@ impl FileConstellation {
 
     #[inline(always)]
     fn construct_path(&self, id: FileIdentifier) -> Result<PathBuf> {
-        let (roots, directories, files): (u64, u64, u64) = self.dimensions();
-
-        let dwidth: usize = ((roots * directories        ).ilog10() + 1) as usize;
-        let fwidth: usize = ((roots * directories * files).ilog10() + 1) as usize;
-
-        let mut location: PathBuf = self.roots[id.root as usize].clone();
+        let fwidth: usize = (self.count().ilog10() + 1) as usize;
 
-        let directory: String = format!("{}{:0width$}", self.directories.prefix, id.directory, width=dwidth);
-        location.push(directory);
+        let mut location: PathBuf = self.directory_path(self.depth() - 1, id.directory);
 
         let file: String = format!("{}{:0width$}", self.files.prefix, id.file, width=fwidth);
         location.push(file);
@@ -275,9 +657,10 @@ impl FileConstellation {
     }
 
     fn create_file(&self, id: FileIdentifier) -> Result<(), anyhow::Error> {
+        let file_id: u64 = id.file;
         let path: PathBuf = self.construct_path(id)?;
         if path.parent().is_some_and(|p| p.exists()) {
-            OpenOptions::new()
+            let file: File = OpenOptions::new()
                         .create(true)
                         .write(true)
                         //.custom_flags(
@@ -285,35 +668,229 @@ impl FileConstellation {
                         //    else              { 0 }
                         //)
                         .truncate(false)
-                        .open(path)?
-                        .set_len(self.files.size.unwrap_or_default())?;
-            Ok(())
+                        .open(&path)?;
+            self.files.options.as_ref()
+                              .map(|o| o.preallocation)
+                              .unwrap_or_default()
+                              .apply(&file, file_id, self.size(file_id))
+                              .map_err(|e| anyhow!("unable to preallocate {}: {e}", path.display()))
         } else {
             Err(anyhow!("attempted to create file but parent directory did not exist"))
         }
     }
 
-    fn create_directory(&self, id: FileIdentifier) -> Result<(), anyhow::Error> {
-         let mut path = self.construct_path(id)?;
-         path.pop(); // pop off the file (last element)
-         std::fs::create_dir(path)?;
-         Ok(())
+    fn create_directory(&self, path: PathBuf) -> Result<(), anyhow::Error> {
+         match std::fs::create_dir(&path) {
+             Err(e) if e.kind() == ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+             created => created.map_err(|e| anyhow!("unable to create directory {}: {e}", path.display())),
+         }
     }
 
     fn remove_file(&self, id: FileIdentifier) -> Result<(), anyhow::Error> {
         let path: PathBuf = self.construct_path(id)?;
-        std::fs::remove_file(path)?;
-        Ok(())
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            removed => removed.map_err(|e| anyhow!("unable to remove file {}: {e}", path.display())),
+        }
     }
 
-    fn remove_directory(&self, id: FileIdentifier) -> Result<(), anyhow::Error> {
-        let mut path: PathBuf = self.construct_path(id)?;
-        path.pop(); // drop file off the end of the buffer
-        std::fs::remove_dir(path)?;
+    fn remove_directory(&self, path: PathBuf) -> Result<(), anyhow::Error> {
+        match std::fs::remove_dir(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            removed => removed.map_err(|e| anyhow!("unable to remove directory {}: {e}", path.display())),
+        }
+    }
+
+}
+
+/// Parses layers of directories, outermost first, of the form `<prefix>:<fan-out>,...`, e.g.
+/// `a:4,b:16,c:2`.
+pub fn parse_layers(s: &str) -> Result<Vec<(String, u64)>> {
+    let invalid = || anyhow!("invalid directory layers '{s}'; expected a form such as 'a:4,b:16'");
+    s.split(',')
+     .map(|layer| {
+         let (prefix, fan_out) = layer.trim().split_once(':').ok_or_else(invalid)?;
+         let fan_out: u64 = fan_out.trim().parse().map_err(|_| invalid())?;
+         if prefix.is_empty() || prefix.contains('/') || fan_out == 0 { return Err(invalid()); }
+         Ok((prefix.to_string(), fan_out))
+     })
+     .collect()
+}
+
+/// A progress bar for a constellation-wide operation over `len` items. Hidden when stderr is not a
+/// terminal.
+fn progress_bar(len: u64, message: &'static str) -> ProgressBar {
+    ProgressBar::new(len).with_message(message)
+                         .with_style(ProgressStyle::with_template("{msg} [{bar:40}] {human_pos}/{human_len} ({per_sec}, {eta})")
+                                                   .expect("progress template is valid")
+                                                   .progress_chars("=> "))
+}
+
+/// The files of a constellation as found on disk. See `FileConstellation::survey`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Survey {
+    /// Number of files which exist with their expected length.
+    pub complete: u64,
+    /// Ids of the files which don't exist.
+    pub missing: Vec<u64>,
+    /// Ids of the files whose length isn't their expected size.
+    pub resized: Vec<u64>,
+    /// Bytes allocated to the files which exist.
+    pub allocated: u64,
+}
+impl Survey {
+    pub fn is_complete(&self) -> bool { self.missing.is_empty() && self.resized.is_empty() }
+}
+impl fmt::Display for Survey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} complete, {} missing and {} resized files; {} allocated",
+               self.complete, self.missing.len(), self.resized.len(), indicatif::HumanBytes(self.allocated))
+    }
+}
+
+/// The location of a byte of a constellation in benchmark terms. See `FileConstellation::address`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageAddress {
+    pub file: u64,
+    /// Index of the page within the file.
+    pub page: u64,
+    /// Index of the chapter, of `PAGES_PER_CHAPTER` pages, within the file.
+    pub chapter: u64,
+    /// Offset of the byte within its page.
+    pub offset: u64,
+}
+impl PageAddress {
+    /// Byte offset of the page within its file.
+    pub fn page_offset(&self) -> u64 { self.page * PAGE_BYTES as u64 }
+
+    /// The page which should be found at this address after it has been mutated `mutations` times.
+    pub fn expected_page(&self, seed: u64, mutations: u64) -> Page<DATA_WORDS> {
+        let mut page: Page<DATA_WORDS> = Page::default();
+        page.reinit(seed, self.file, self.page, mutations);
+        page
+    }
+}
+impl fmt::Display for PageAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "file {}, chapter {}, page {}, byte {} of the page", self.file, self.chapter, self.page, self.offset)
+    }
+}
+
+/// How a thread obtains file handles from a `FileConstellation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Handles {
+    /// Open, and later close, the file for every operation. This keeps the cost of `open` and
+    /// `close` in the measured loop.
+    Reopen,
+    /// Keep up to this many files open, closing the least recently used when full.
+    Cached(usize),
+}
+impl Handles {
+    /// File descriptors left for everything other than cached handles.
+    const RESERVED_DESCRIPTORS: u64 = 64;
+
+    /// A per-thread cache which, across `threads` threads, stays within the soft limit on open
+    /// files.
+    pub fn cached_within_limit(threads: usize) -> Result<Handles> {
+        let available: u64 = open_file_limit()?.saturating_sub(Handles::RESERVED_DESCRIPTORS);
+        let capacity: u64 = available / threads.max(1) as u64;
+        if capacity == 0 {
+            return Err(anyhow!("open file limit is too low to cache handles for {threads} threads"));
+        }
+        Ok(Handles::Cached(capacity as usize))
+    }
+
+    /// Checks that `threads` caches of this size fit within the soft limit on open files.
+    pub fn validate(&self, threads: usize) -> Result<()> {
+        if let Handles::Cached(capacity) = self {
+            let limit: u64 = open_file_limit()?;
+            let wanted: u64 = (capacity.saturating_mul(threads) as u64).saturating_add(Handles::RESERVED_DESCRIPTORS);
+            if *capacity == 0 || wanted > limit {
+                return Err(anyhow!("cannot cache {capacity} handles for each of {threads} threads; \
+                                    must be non-zero and the total, plus {}, within RLIMIT_NOFILE ({limit})",
+                                   Handles::RESERVED_DESCRIPTORS));
+            }
+        }
         Ok(())
     }
+}
+
+/// The soft limit on open file descriptors for this process.
+fn open_file_limit() -> Result<u64> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes to the provided struct.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(anyhow!("unable to read RLIMIT_NOFILE: {}", std::io::Error::last_os_error()));
+    }
+    Ok(limit.rlim_cur)
+}
+
+/// # Overview
+/// Open file handles for a single thread, keyed by file id and access mode. Depending on
+/// `Handles` the file is either re-opened for every request or kept open until it is the least
+/// recently used file and the cache is full.
+///
+/// Handles are shared between operations, so their offset is whatever the previous operation
+/// left it at. Callers should use positional IO (see `engine::Engine`).
+pub struct HandleCache<'a> {
+    files: &'a FileConstellation,
+    policy: Handles,
+    /// (id, read, write) to the open file and when it was last used.
+    open: HashMap<(u64, bool, bool), (File, u64)>,
+    /// Last use to key; the first entry is the least recently used file.
+    recency: BTreeMap<u64, (u64, bool, bool)>,
+    uses: u64,
+    /// Number of files opened so far.
+    opens: u64,
+}
+impl<'a> HandleCache<'a> {
+    pub fn new(files: &'a FileConstellation, policy: Handles) -> HandleCache<'a> {
+        HandleCache { files, policy, open: HashMap::new(), recency: BTreeMap::new(), uses: 0, opens: 0 }
+    }
 
+    /// Returns a handle to file `id` opened for (read|write). When re-opening, the file is opened
+    /// afresh on every call, even if the previous call was for the same file.
+    pub fn get(&mut self, id: u64, read: bool, write: bool) -> Result<&mut File> {
+        let key: (u64, bool, bool) = (id, read, write);
+        self.uses += 1;
+
+        let capacity: usize = match self.policy {
+            Handles::Reopen => {
+                // Closes the file handed out by the previous call.
+                self.open.clear();
+                self.recency.clear();
+                0
+            },
+            Handles::Cached(capacity) => capacity,
+        };
+
+        if let Some((_, used)) = self.open.get_mut(&key) {
+            self.recency.remove(used);
+            *used = self.uses;
+        } else {
+            while !self.open.is_empty() && self.open.len() >= capacity.max(1) {
+                let (_, oldest) = self.recency.pop_first().expect("every open file has a recency");
+                self.open.remove(&oldest); // closes the file
+            }
+            self.open.insert(key, (self.files.open(id, read, write)?, self.uses));
+            self.opens += 1;
+        }
+        self.recency.insert(self.uses, key);
+
+        Ok(&mut self.open.get_mut(&key).expect("file was just inserted").0)
+    }
+
+    /// Whether `get` would return a file which is already open rather than open it.
+    pub fn is_open(&self, id: u64, read: bool, write: bool) -> bool {
+        self.policy != Handles::Reopen && self.open.contains_key(&(id, read, write))
+    }
+
+    /// Number of files currently open.
+    pub fn len(&self) -> usize { self.open.len() }
+
+    pub fn is_empty(&self) -> bool { self.open.is_empty() }
 }
+
 impl Drop for FileConstellation {
     fn drop(&mut self) {
         if self.drop {
@@ -329,7 +906,6 @@ impl fmt::Display for FileConstellation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let (roots, directories, files): (u64, u64, u64) = self.dimensions();
 
-        let dwidth: usize = ((roots * directories        ).ilog10() + 1) as usize;
         let fwidth: usize = ((roots * directories * files).ilog10() + 1) as usize;
         //let root_id = id.file % self.roots.len() as u64;
 
@@ -341,17 +917,24 @@ impl fmt::Display for FileConstellation {
             writeln!(f, "\t{}", r.display())
         })?;
 
-        writeln!(f, "Directory Names:.. {}[{:0width$}-{:0width$}]\n", 
-                    self.directories.prefix,
-                    0,
-                    (roots * directories) - 1,
-                    width = dwidth)?;
+        self.directories.iter().enumerate().try_for_each(|(layer, info)| {
+            let count: u64 = self.layer_count(layer);
+            writeln!(f, "Directory Names:.. {}[{:0width$}-{:0width$}] ({} per parent)\n", 
+                        info.prefix,
+                        0,
+                        count - 1,
+                        info.count,
+                        width = (count.ilog10() + 1) as usize)
+        })?;
 
         writeln!(f, "File Names:....... {}[{:0width$}-{:0width$}]\n", 
                     self.files.prefix,
                     0,
                     (roots * directories * files) - 1,
                     width = fwidth)?;
+
+        writeln!(f, "File Sizes:....... {}", self.sizes())?;
+        writeln!(f, "File Placement:... {}", self.placement())?;
         Ok(())
     }
 }
@@ -362,7 +945,7 @@ pub mod tests {
     #[allow(unused)]
     use serial_test::serial;
     #[allow(unused)]
-    use std::io::Write;
+    use std::io::{Read, Write};
 
     #[test]
     #[serial]
@@ -379,8 +962,8 @@ pub mod tests {
             vec![root_a.into_path(),root_b.into_path()],
             ("test_dir".to_string(), dcount),
             ("test_file".to_string(),fcount),
-            fsize,
-            FileOptions { directo_io: false },
+            FileSizes::fixed(fsize).unwrap(),
+            FileOptions { directo_io: false, ..Default::default() },
             false
         ).expect("created directories and files");
 
@@ -410,4 +993,340 @@ pub mod tests {
         // destroy is used by drop so this ensures Drop trait is functional
         files.destroy().expect("Unable to destroy FileConstellation");
     }
+
+    #[test]
+    #[serial]
+    fn instantiation_resumes_and_teardown_tolerates_missing_items() {
+        use tempfile::tempdir;
+
+        let root = tempdir().expect("crate should be able to create temporary directories");
+        let constellation = |drop: bool| FileConstellation::new(
+            vec![root.path().to_path_buf()],
+            ("test_dir".to_string(), 3),
+            ("test_file".to_string(), 6),
+            FileSizes::fixed(1024).unwrap(),
+            FileOptions { directo_io: false, ..Default::default() },
+            drop
+        );
+
+        // A constellation left behind is reused, as if creation had been interrupted.
+        let kept: FileConstellation = constellation(false).expect("created directories and files");
+        let partial: PathBuf = kept.construct_path(kept.file_identifier(4).unwrap()).unwrap();
+        std::fs::remove_file(&partial).unwrap();
+        let files: FileConstellation = constellation(true).expect("resumed creation");
+        assert_eq!(partial.metadata().unwrap().len(), 1024);
+
+        // Missing and resized files are found without being created.
+        std::fs::remove_file(&partial).unwrap();
+        OpenOptions::new().write(true).open(files.path(1).unwrap()).unwrap().set_len(10).unwrap();
+        let survey: Survey = files.survey().unwrap();
+        assert_eq!((survey.complete, survey.missing, survey.resized), (16, vec![4], vec![1]));
+        assert!(!partial.exists());
+
+        // Teardown skips what is already gone.
+        files.destroy().expect("missing files are skipped");
+        files.destroy().expect("missing directories are skipped");
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 0);
+
+        // Something which can't be removed is reported, but everything else still is.
+        let files: FileConstellation = constellation(true).expect("created directories and files");
+        let blocker: PathBuf = files.directory(0).unwrap().join("blocker");
+        std::fs::write(&blocker, b"").unwrap();
+        assert!(files.destroy().is_err());
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 1);
+        std::fs::remove_file(&blocker).unwrap();
+
+        // Removal doesn't depend on ownership.
+        kept.remove().expect("removed directories and files");
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    #[serial]
+    fn preallocation_strategies() {
+        use std::os::unix::fs::MetadataExt;
+        use tempfile::tempdir;
+
+        // Not a multiple of the page size, so the last page is partial.
+        let size: u64 = 3 * PAGE_BYTES as u64 + 64;
+        let root = tempdir().expect("crate should be able to create temporary directories");
+        let constellation = |preallocation: Preallocation| FileConstellation::new(
+            vec![root.path().to_path_buf()],
+            ("test_dir".to_string(), 1),
+            ("test_file".to_string(), 2),
+            FileSizes::fixed(size).unwrap(),
+            FileOptions { directo_io: false, preallocation, ..Default::default() },
+            true
+        ).expect("created directories and files");
+        let contents = |files: &FileConstellation, id: u64| -> Vec<u8> {
+            let mut bytes: Vec<u8> = Vec::new();
+            files.open(id, true, false).unwrap().read_to_end(&mut bytes).unwrap();
+            bytes
+        };
+
+        let files = constellation(Preallocation::Sparse);
+        let sparse = files.open(0, true, false).unwrap().metadata().unwrap();
+        assert_eq!((sparse.len(), sparse.blocks()), (size, 0));
+        drop(files);
+
+        let files = constellation(Preallocation::Allocate);
+        let allocated = files.open(0, true, false).unwrap().metadata().unwrap();
+        assert_eq!(allocated.len(), size);
+        assert!(allocated.blocks() * 512 >= size);
+        drop(files);
+
+        let files = constellation(Preallocation::AllocateKeepSize);
+        let kept = files.open(0, true, false).unwrap().metadata().unwrap();
+        assert_eq!(kept.len(), 0);
+        assert!(kept.blocks() > 0);
+        drop(files);
+
+        let files = constellation(Preallocation::Zero);
+        assert!(contents(&files, 1).iter().all(|b| *b == 0) && contents(&files, 1).len() == size as usize);
+        drop(files);
+
+        let files = constellation(Preallocation::PunchHole);
+        let punched = files.open(0, true, false).unwrap().metadata().unwrap();
+        // Only whole blocks are deallocated, so the block holding the partial last page remains.
+        assert_eq!(punched.len(), size);
+        assert!(punched.blocks() < allocated.blocks());
+        drop(files);
+
+        // Pages are valid for the seed and file they belong to, including the partial last page.
+        let files = constellation(Preallocation::Pages(0x5EED));
+        let bytes: Vec<u8> = contents(&files, 1);
+        assert_eq!(bytes.len(), size as usize);
+        bytes.chunks(PAGE_BYTES).enumerate().for_each(|(p, chunk)| {
+            let mut page: Page<DATA_WORDS> = Page::default();
+            bytemuck::bytes_of_mut(&mut page)[..chunk.len()].copy_from_slice(chunk);
+            assert!(page.is_valid_upto(chunk.len()));
+            assert_eq!(page.get_metadata(), (0x5EED, 1, p as u64, 0));
+        });
+
+        // Punching a hole deallocates the range, which then reads as zeroes.
+        files.punch_hole(1, PAGE_BYTES as u64, PAGE_BYTES as u64).unwrap();
+        assert!(contents(&files, 1)[PAGE_BYTES..2 * PAGE_BYTES].iter().all(|b| *b == 0));
+        assert_eq!(contents(&files, 1)[..PAGE_BYTES], bytes[..PAGE_BYTES]);
+
+        assert_eq!("fallocate-keep-size".parse::<Preallocation>().unwrap(), Preallocation::AllocateKeepSize);
+        assert!("eventually".parse::<Preallocation>().is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn nested_layers() {
+        use tempfile::tempdir;
+
+        let root_a = tempdir().expect("crate should be able to create temporary directories");
+        let root_b = tempdir().expect("crate should be able to create temporary directories");
+        let files: FileConstellation = FileConstellation::nested(
+            vec![root_a.path().to_path_buf(), root_b.path().to_path_buf()],
+            vec![("a".to_string(), 2), ("b".to_string(), 3), ("c".to_string(), 1)],
+            ("test_file".to_string(), 2),
+            FileSizes::fixed(64).unwrap(),
+            FileOptions { directo_io: false, ..Default::default() },
+            true
+        ).expect("created directories and files");
+        assert_eq!((files.depth(), files.dimensions(), files.count()), (3, (2, 6, 2), 24));
+
+        // Every file is at the full depth, beneath the directories congruent to its id.
+        let paths: Vec<PathBuf> = (0..files.count()).map(|id| files.construct_path(files.file_identifier(id).unwrap()).unwrap()).collect();
+        paths.iter().enumerate().for_each(|(id, path)| {
+            let id: u64 = id as u64;
+            let root: &std::path::Path = [root_a.path(), root_b.path()][id as usize % 2];
+            let expected: PathBuf = root.join(format!("a{}", id % 4))
+                                        .join(format!("b{:02}", id % 12))
+                                        .join(format!("c{:02}", id % 12))
+                                        .join(format!("test_file{id:02}"));
+            assert_eq!(*path, expected);
+            assert!(path.is_file());
+        });
+        assert_eq!(std::fs::read_dir(root_a.path().join("a0")).unwrap().count(), 3);
+        assert_eq!(parse_layers("a:2,b:3").unwrap(), vec![("a".to_string(), 2), ("b".to_string(), 3)]);
+        assert!(parse_layers("a:0").is_err() && parse_layers("a/b:2").is_err() && parse_layers("a").is_err());
+
+        drop(files);
+        assert_eq!(std::fs::read_dir(root_a.path()).unwrap().count() + std::fs::read_dir(root_b.path()).unwrap().count(), 0);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn ids_and_paths_are_a_bijection(roots in 1u64..4,
+                                         fan_outs in proptest::collection::vec(1u64..4, 1..4),
+                                         files in 1u64..6,
+                                         policy in 0usize..5,
+                                         weights in proptest::collection::vec(0u64..4, 3),
+                                         mapping in proptest::collection::vec(0usize..3, 1..8),
+                                         seed: u64) {
+            use crate::placement::{FilePlacement, RootMap};
+            use proptest::{prop_assert, prop_assert_eq};
+            use std::collections::HashSet;
+
+            let mut weights: Vec<u64> = weights[..roots as usize].to_vec();
+            weights[0] += 1;
+            let placement: FilePlacement = match policy {
+                0 => FilePlacement::RoundRobin,
+                1 => FilePlacement::Contiguous,
+                2 => FilePlacement::Weighted(weights),
+                3 => FilePlacement::Hashed(seed),
+                _ => FilePlacement::Mapped(RootMap::new(mapping.iter().map(|r| r % roots as usize).collect()).unwrap()),
+            };
+            placement.validate(roots).unwrap();
+            let balanced: bool = matches!(placement, FilePlacement::RoundRobin | FilePlacement::Contiguous);
+
+            // Never instantiated, and never dropped with teardown, so the roots need not exist.
+            let root_paths: Vec<PathBuf> = (0..roots).map(|r| PathBuf::from(format!("/smio-root{r}"))).collect();
+            let constellation: FileConstellation = FileConstellation::layout(
+                root_paths.clone(),
+                fan_outs.iter().enumerate().map(|(l, f)| (format!("layer{}_", l), *f)).collect(),
+                ("file".to_string(), files),
+                FileSizes::fixed(64).unwrap(),
+                FileOptions { placement, ..Default::default() },
+                false
+            );
+            let count: u64 = constellation.count();
+            prop_assert_eq!(count, roots * fan_outs.iter().product::<u64>() * files);
+
+            // Every directory of every layer has a distinct path within its parent in the layer above.
+            (0..constellation.depth()).for_each(|layer| {
+                let directories: HashSet<PathBuf> = (0..constellation.layer_count(layer))
+                    .map(|d| constellation.directory_path(layer, d))
+                    .collect();
+                assert_eq!(directories.len() as u64, constellation.layer_count(layer));
+                if layer > 0 {
+                    directories.iter().for_each(|d| {
+                        let parent: &Path = d.parent().unwrap();
+                        assert!((0..constellation.layer_count(layer - 1)).any(|p| constellation.directory_path(layer - 1, p) == parent));
+                    });
+                }
+            });
+
+            let mut paths: HashSet<PathBuf> = HashSet::new();
+            let mut per_directory: HashMap<u64, u64> = HashMap::new();
+            for id in 0..count {
+                let path: PathBuf = constellation.path(id).unwrap();
+                let directory: u64 = constellation.file_identifier(id).unwrap().directory;
+                prop_assert!(directory < constellation.directory_count());
+                let parent: PathBuf = constellation.directory(directory).unwrap();
+                prop_assert_eq!(path.parent().unwrap(), parent.as_path());
+                prop_assert!(path.starts_with(&root_paths[(directory % roots) as usize]));
+                prop_assert!(paths.insert(path.clone()), "file {} collides at {}", id, path.display());
+                prop_assert_eq!(constellation.identify(&path).unwrap(), id);
+                *per_directory.entry(directory).or_default() += 1;
+            }
+            if balanced {
+                prop_assert!(per_directory.len() as u64 == constellation.directory_count() && per_directory.values().all(|n| *n == files));
+            }
+
+            prop_assert!(constellation.path(count).is_err());
+            let beyond: PathBuf = root_paths[0].join(format!("file{count}"));
+            prop_assert!(constellation.identify(&beyond).is_err());
+            prop_assert!(constellation.identify(&root_paths[0].join("notes.txt")).is_err());
+            if constellation.directory_count() > 1 {
+                // The right name in the wrong directory.
+                let misplaced: PathBuf = constellation.directory((constellation.file_identifier(0).unwrap().directory + 1) % constellation.directory_count())
+                                                      .unwrap()
+                                                      .join(constellation.path(0).unwrap().file_name().unwrap());
+                prop_assert!(constellation.identify(&misplaced).is_err());
+            }
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn address_of_path_and_offset() {
+        use tempfile::tempdir;
+
+        const SEED: u64 = 0xADD2E55;
+        let root = tempdir().expect("crate should be able to create temporary directories");
+        let files: FileConstellation = FileConstellation::new(
+            vec![root.path().to_path_buf()],
+            ("test_dir".to_string(), 2),
+            ("test_file".to_string(), 2),
+            FileSizes::fixed(300 * PAGE_BYTES as u64).unwrap(),
+            FileOptions { directo_io: false, preallocation: Preallocation::Pages(SEED), ..Default::default() },
+            true
+        ).expect("created directories and files");
+
+        let path: PathBuf = files.path(3).unwrap();
+        let offset: u64 = 257 * PAGE_BYTES as u64 + 100;
+        let address: PageAddress = files.address(&path, offset).unwrap();
+        assert_eq!(address, PageAddress { file: 3, page: 257, chapter: 1, offset: 100 });
+
+        // The regenerated page is what is on disk.
+        let mut on_disk: Page<DATA_WORDS> = Page::default();
+        files.open(3, true, false).unwrap().read_exact_at(bytemuck::bytes_of_mut(&mut on_disk), address.page_offset()).unwrap();
+        assert!(on_disk == address.expected_page(SEED, 0));
+
+        // A relative spelling of the same path is resolved.
+        let relative: PathBuf = root.path().join("test_dir1").join("..").join(path.strip_prefix(root.path()).unwrap());
+        assert_eq!(files.address(&relative, 0).unwrap().file, 3);
+
+        assert!(files.address(&path, 300 * PAGE_BYTES as u64).is_err());
+        assert!(files.address(&root.path().join("elsewhere"), 0).is_err());
+
+        // Describing a constellation neither creates nor removes anything.
+        assert!(FileConstellation::describe(
+            vec![root.path().to_path_buf()], vec![("a/b".to_string(), 2)], ("test_file".to_string(), 2), FileSizes::fixed(64).unwrap(), FileOptions::default()
+        ).is_err());
+        assert!(FileConstellation::describe(
+            vec![root.path().to_path_buf()], vec![("test_dir".to_string(), 2)], ("test_file".to_string(), 32), "uniform:1K-17179869183G".parse().unwrap(), FileOptions::default()
+        ).is_err());
+
+        let described: FileConstellation = FileConstellation::describe(
+            vec![root.path().to_path_buf()],
+            vec![("test_dir".to_string(), 2)],
+            ("test_file".to_string(), 2),
+            FileSizes::fixed(300 * PAGE_BYTES as u64).unwrap(),
+            FileOptions::default()
+        ).unwrap();
+        assert_eq!(described.address(&path, offset).unwrap(), address);
+        let described: FileConstellation = described.create().expect("creation reuses existing files");
+        described.sync().unwrap();
+        described.drop_cache().unwrap();
+        drop(described);
+        assert!(path.exists());
+    }
+
+    #[test]
+    #[serial]
+    fn handle_cache_evicts_least_recently_used() {
+        use tempfile::tempdir;
+
+        let root = tempdir().expect("crate should be able to create temporary directories");
+        let files: FileConstellation = FileConstellation::new(
+            vec![root.path().to_path_buf()],
+            ("test_dir".to_string(), 1),
+            ("test_file".to_string(), 4),
+            FileSizes::fixed(1024).unwrap(),
+            FileOptions { directo_io: false, ..Default::default() },
+            true
+        ).expect("created directories and files");
+
+        let mut handles = HandleCache::new(&files, Handles::Cached(2));
+        handles.get(0, true, false).unwrap();
+        handles.get(1, true, false).unwrap();
+        handles.get(0, true, false).unwrap();
+        assert_eq!(handles.len(), 2);
+
+        // File 1 is the least recently used and is the one closed.
+        handles.get(2, true, false).unwrap();
+        assert_eq!(handles.len(), 2);
+        assert!(handles.open.contains_key(&(0, true, false)));
+        assert!(handles.open.contains_key(&(2, true, false)));
+
+        // Re-opening keeps only the current file open.
+        let mut handles = HandleCache::new(&files, Handles::Reopen);
+        (0..4).for_each(|id| { handles.get(id, true, false).unwrap(); });
+        assert_eq!(handles.len(), 1);
+        // Even when the same file is asked for again.
+        (0..2).for_each(|_| {
+            assert!(!handles.is_open(3, true, false));
+            handles.get(3, true, false).unwrap();
+        });
+        assert_eq!((handles.opens, handles.len()), (6, 1));
+
+        assert!(Handles::Cached(usize::MAX / 2).validate(2).is_err());
+        assert!(Handles::cached_within_limit(1).unwrap().validate(1).is_ok());
+    }
 }
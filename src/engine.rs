@@ -0,0 +1,156 @@
+use std::{
+    fmt,
+    fs::File,
+    io,
+    os::{fd::AsRawFd, unix::fs::FileExt},
+    str::FromStr,
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+
+/// Maximum number of vectors the kernel accepts in a single `preadv`/`pwritev` (`UIO_MAXIOV`).
+const UIO_MAXIOV: usize = 1024;
+
+/// # Overview
+/// The interface used to move data between a buffer and a file. All engines use positional IO:
+/// the offset is given with each operation and the file's own offset is never used. A single
+/// descriptor may therefore be shared between threads without coordination.
+///
+/// # Posix
+/// Buffers no larger than a `segment` (a page) are transferred with `pread`/`pwrite`. Larger
+/// buffers, such as a multi-page chapter, are transferred with `preadv`/`pwritev` using one
+/// vector per segment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Engine {
+    #[default]
+    Posix,
+}
+impl Engine {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Engine::Posix => "posix",
+        }
+    }
+
+    /// Reads into `buffer` from `offset` with a single system call. Returns the number of bytes
+    /// read, which may be fewer than requested.
+    pub fn read_at(&self, file: &File, buffer: &mut [u8], offset: u64, segment: usize) -> io::Result<usize> {
+        match self {
+            Engine::Posix if buffer.len() <= segment => file.read_at(buffer, offset),
+            Engine::Posix => {
+                let vectors: Vec<libc::iovec> = buffer.chunks_mut(segment.max(1))
+                                                      .take(UIO_MAXIOV)
+                                                      .map(|chunk| libc::iovec {
+                                                          iov_base: chunk.as_mut_ptr() as *mut libc::c_void,
+                                                          iov_len: chunk.len(),
+                                                      })
+                                                      .collect();
+                // SAFETY: every vector points into `buffer`, which is exclusively borrowed for
+                // the duration of the call.
+                let ret: isize = unsafe {
+                    libc::preadv(file.as_raw_fd(), vectors.as_ptr(), vectors.len() as libc::c_int, offset as libc::off_t)
+                };
+                if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret as usize) }
+            },
+        }
+    }
+
+    /// Writes `buffer` to `offset` with a single system call. Returns the number of bytes
+    /// written, which may be fewer than requested.
+    pub fn write_at(&self, file: &File, buffer: &[u8], offset: u64, segment: usize) -> io::Result<usize> {
+        match self {
+            Engine::Posix if buffer.len() <= segment => file.write_at(buffer, offset),
+            Engine::Posix => {
+                let vectors: Vec<libc::iovec> = buffer.chunks(segment.max(1))
+                                                      .take(UIO_MAXIOV)
+                                                      .map(|chunk| libc::iovec {
+                                                          iov_base: chunk.as_ptr() as *mut libc::c_void,
+                                                          iov_len: chunk.len(),
+                                                      })
+                                                      .collect();
+                // SAFETY: every vector points into `buffer`, which outlives the call and is only
+                // read from.
+                let ret: isize = unsafe {
+                    libc::pwritev(file.as_raw_fd(), vectors.as_ptr(), vectors.len() as libc::c_int, offset as libc::off_t)
+                };
+                if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret as usize) }
+            },
+        }
+    }
+
+    /// Writes all of `buffer` to `offset`, issuing further writes if one is short.
+    pub fn write_all_at(&self, file: &File, buffer: &[u8], offset: u64, segment: usize) -> io::Result<()> {
+        let mut written: usize = 0;
+        while written < buffer.len() {
+            match self.write_at(file, &buffer[written..], offset + written as u64, segment) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole buffer")),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads until `buffer` is full or the end of the file is reached. Returns the number of
+    /// bytes read.
+    pub fn read_all_at(&self, file: &File, buffer: &mut [u8], offset: u64, segment: usize) -> io::Result<usize> {
+        let mut read: usize = 0;
+        while read < buffer.len() {
+            match self.read_at(file, &mut buffer[read..], offset + read as u64, segment) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(read)
+    }
+}
+impl FromStr for Engine {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Engine> {
+        match s.trim() {
+            "posix" => Ok(Engine::Posix),
+            "direct_io" | "mmap" | "libaio" | "io_uring" => Err(anyhow!("the {s} engine is not implemented; expected 'posix'")),
+            _ => Err(anyhow!("invalid engine '{s}'; expected 'posix'")),
+        }
+    }
+}
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn vectored_and_single_transfers_agree() {
+        const SEGMENT: usize = 512;
+        let file: File = tempfile::tempfile().expect("crate should be able to create temporary files");
+        let engine: Engine = Engine::Posix;
+
+        // Four segments written with pwritev at an offset, one with pwrite after them.
+        let data: Vec<u8> = (0..4 * SEGMENT).map(|b| (b % 251) as u8).collect();
+        engine.write_all_at(&file, &data, SEGMENT as u64, SEGMENT).unwrap();
+        engine.write_all_at(&file, &data[..SEGMENT], 5 * SEGMENT as u64, SEGMENT).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 6 * SEGMENT as u64);
+
+        let mut buffer: Vec<u8> = vec![0; 4 * SEGMENT];
+        assert_eq!(engine.read_all_at(&file, &mut buffer, SEGMENT as u64, SEGMENT).unwrap(), 4 * SEGMENT);
+        assert_eq!(buffer, data);
+
+        // Reading past the end of the file is short rather than an error.
+        assert_eq!(engine.read_all_at(&file, &mut buffer, 4 * SEGMENT as u64, SEGMENT).unwrap(), 2 * SEGMENT);
+        assert_eq!(&buffer[SEGMENT..2 * SEGMENT], &data[..SEGMENT]);
+
+        assert_eq!("posix".parse::<Engine>().unwrap(), Engine::Posix);
+        assert!("io_uring".parse::<Engine>().is_err());
+    }
+}
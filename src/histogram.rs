@@ -0,0 +1,238 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+
+/// Each power of two is split into `2^SUB_BUCKET_BITS` buckets, so a recorded value is within
+/// 1/128th, under 1%, of the true one.
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+/// Enough buckets for every `u64`.
+const BUCKETS: usize = ((64 - SUB_BUCKET_BITS + 1) as usize) << SUB_BUCKET_BITS;
+
+/// Percentiles shown in reports.
+pub const PERCENTILES: [f64; 5] = [50.0, 90.0, 99.0, 99.9, 99.99];
+
+/// # Overview
+/// A log-bucketed histogram of latencies in nanoseconds, in the style of HdrHistogram. Values
+/// below `2^SUB_BUCKET_BITS` are counted exactly; above that each power of two has as many
+/// buckets, so the relative error is the same whatever the magnitude:
+///
+/// ```txt
+///    value    0 1 2 .. 127 | 128 .. 255 | 256 258 .. 510 | 512 516 .. 1020 | ...
+///    bucket   0 1 2 .. 127 | 128 .. 255 | 256 257 .. 383 | 384 385 .. 511   | ...
+/// ```
+///
+/// Histograms of the same operation, from any threads or runs, merge by adding their buckets.
+/// They serialize sparsely, as `[bucket, count]` pairs, so a saved histogram holds only the
+/// buckets which were used.
+///
+/// ```
+/// use std::time::Duration;
+/// use super_massive_io::histogram::Histogram;
+///
+/// let mut fast = Histogram::new();
+/// (0..99).for_each(|_| fast.record(Duration::from_micros(100)));
+/// let mut slow = Histogram::new();
+/// slow.record(Duration::from_millis(20));
+///
+/// fast.merge(&slow);
+/// assert_eq!(fast.count(), 100);
+/// assert!(fast.percentile(50.0) < Duration::from_micros(101));
+/// assert_eq!(fast.percentile(99.9), Duration::from_millis(20));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "Encoded", try_from = "Encoded")]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    /// Exact extremes and sum, in nanoseconds, which the buckets only approximate.
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram { counts: vec![0; BUCKETS], count: 0, min: u64::MAX, max: 0, sum: 0 }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let value: u64 = latency.as_nanos().min(u64::MAX as u128) as u64;
+        self.counts[bucket(value)] += 1;
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value as u128;
+    }
+
+    /// Adds every value recorded by `other`.
+    pub fn merge(&mut self, other: &Histogram) {
+        self.counts.iter_mut().zip(&other.counts).for_each(|(count, other)| *count += other);
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+    }
+
+    pub fn count(&self) -> u64 { self.count }
+
+    pub fn max(&self) -> Duration { Duration::from_nanos(self.max) }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 { Duration::ZERO } else { Duration::from_nanos((self.sum / self.count as u128) as u64) }
+    }
+
+    /// The latency which `percentile` percent of operations took no longer than; the highest
+    /// value of its bucket, but never more than the longest latency recorded.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.count == 0 { return Duration::ZERO; }
+        let rank: u64 = ((percentile.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen: u64 = 0;
+        let index: usize = self.counts.iter()
+                                      .position(|count| { seen += count; seen >= rank })
+                                      .expect("rank is at most the count");
+        Duration::from_nanos(highest(index).clamp(self.min, self.max))
+    }
+}
+impl Default for Histogram {
+    fn default() -> Histogram { Histogram::new() }
+}
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percentiles: Vec<String> = PERCENTILES.iter()
+                                                  .map(|p| format!("p{p} {:?}", self.percentile(*p)))
+                                                  .collect();
+        write!(f, "{}", percentiles.join(" "))
+    }
+}
+
+/// The bucket counting `value`.
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS { return value as usize; }
+    let shift: u32 = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+    (((shift + 1) as u64 * SUB_BUCKETS) + (value >> shift) - SUB_BUCKETS) as usize
+}
+
+/// The highest value counted by bucket `index`.
+fn highest(index: usize) -> u64 {
+    let index: u64 = index as u64;
+    if index < SUB_BUCKETS { return index; }
+    let shift: u64 = index / SUB_BUCKETS - 1;
+    let lowest: u64 = (SUB_BUCKETS + index % SUB_BUCKETS) << shift;
+    lowest + ((1u64 << shift) - 1)
+}
+
+/// How a `Histogram` is saved: only its used buckets, and how finely it was bucketed so that
+/// histograms recorded differently are never merged.
+#[derive(Serialize, Deserialize)]
+struct Encoded {
+    sub_bucket_bits: u32,
+    count: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+    buckets: Vec<(usize, u64)>,
+}
+impl From<Histogram> for Encoded {
+    fn from(histogram: Histogram) -> Encoded {
+        Encoded {
+            sub_bucket_bits: SUB_BUCKET_BITS,
+            count: histogram.count,
+            min: histogram.min,
+            max: histogram.max,
+            sum: histogram.sum,
+            buckets: histogram.counts.iter().copied().enumerate().filter(|(_, count)| *count > 0).collect(),
+        }
+    }
+}
+impl TryFrom<Encoded> for Histogram {
+    type Error = anyhow::Error;
+
+    fn try_from(encoded: Encoded) -> Result<Histogram> {
+        if encoded.sub_bucket_bits != SUB_BUCKET_BITS {
+            return Err(anyhow!("histogram has {} sub-bucket bits but {SUB_BUCKET_BITS} are supported", encoded.sub_bucket_bits));
+        }
+        let mut histogram = Histogram { count: encoded.count, min: encoded.min, max: encoded.max, sum: encoded.sum, ..Histogram::new() };
+        for (index, count) in encoded.buckets {
+            *histogram.counts.get_mut(index).ok_or_else(|| anyhow!("histogram bucket {index} is out of range"))? += count;
+        }
+        if histogram.counts.iter().sum::<u64>() != histogram.count {
+            return Err(anyhow!("histogram buckets don't add up to its count of {}", histogram.count));
+        }
+        Ok(histogram)
+    }
+}
+
+/// The latency histogram of one operation of one phase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recorded {
+    pub phase: String,
+    pub operation: String,
+    pub histogram: Histogram,
+}
+
+/// The histograms of every phase of a run and the JSON file they are saved to.
+#[derive(Debug, Clone)]
+pub struct Histograms {
+    path: PathBuf,
+    recorded: Vec<Recorded>,
+}
+impl Histograms {
+    pub fn new(path: &Path) -> Histograms {
+        Histograms { path: path.to_path_buf(), recorded: Vec::new() }
+    }
+
+    /// Reads histograms saved by `extend`.
+    pub fn load(path: &Path) -> Result<Vec<Recorded>> {
+        let contents: String = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("unable to read histograms {}: {e}", path.display()))?;
+        serde_json::from_str(&contents).map_err(|e| anyhow!("invalid histograms {}: {e}", path.display()))
+    }
+
+    /// Adds the histograms of a phase and saves them all, so that the phases already run are
+    /// kept should a later one fail.
+    pub fn extend(&mut self, recorded: impl IntoIterator<Item = Recorded>) -> Result<()> {
+        self.recorded.extend(recorded);
+        let contents: String = serde_json::to_string(&self.recorded)?;
+        std::fs::write(&self.path, contents + "\n")
+            .map_err(|e| anyhow!("unable to write histograms to {}: {e}", self.path.display()))
+    }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn buckets_bound_the_error_and_survive_saving() {
+        // Every value lands in a bucket whose range holds it and is under 1% wide.
+        for value in (0..4096).chain([1_000_003, 123_456_789_012, u64::MAX]) {
+            let index: usize = bucket(value);
+            assert!(index < BUCKETS && highest(index) >= value, "value {value}");
+            assert!(index == 0 || highest(index - 1) < value, "value {value}");
+            assert!(highest(index) - value <= value / SUB_BUCKETS, "value {value}");
+        }
+
+        let mut histogram = Histogram::new();
+        (1..=1000).for_each(|us| histogram.record(Duration::from_micros(us)));
+        let near = |percentile: f64, us: u64| {
+            let value: u128 = histogram.percentile(percentile).as_nanos();
+            let expected: u128 = Duration::from_micros(us).as_nanos();
+            value >= expected && value <= expected + expected / SUB_BUCKETS as u128
+        };
+        assert!(near(50.0, 500) && near(99.0, 990) && near(99.9, 999));
+        assert_eq!(histogram.percentile(100.0), Duration::from_micros(1000));
+        assert_eq!(histogram.mean(), Duration::from_nanos(500_500));
+        assert_eq!(Histogram::new().percentile(99.0), Duration::ZERO);
+
+        let saved: String = serde_json::to_string(&histogram).unwrap();
+        assert_eq!(serde_json::from_str::<Histogram>(&saved).unwrap(), histogram);
+        assert!(serde_json::from_str::<Histogram>(&saved.replace("\"sub_bucket_bits\":7", "\"sub_bucket_bits\":5")).is_err());
+        assert!(serde_json::from_str::<Histogram>(&saved.replace("\"count\":1000", "\"count\":999")).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use std::{fmt, path::Path, str::FromStr};
+use anyhow::{anyhow, Context, Result};
+use super::workload::{ChapterMap, Extent, Operation};
+
+
+/// Bytes in a sector as counted by blktrace, whatever the device's own sector size.
+const SECTOR_BYTES: u64 = 512;
+
+/// blktrace actions, in order of preference, of which one is taken from a trace. Every request
+/// passes through several, so taking more than one would repeat it.
+const BLKPARSE_ACTIONS: [&str; 3] = ["Q", "D", "C"];
+
+/// Formats of IO log which can be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// fio's `write_iolog`, version 2 or 3.
+    Fio,
+    /// The default text output of `blkparse`.
+    Blkparse,
+}
+impl Format {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Format::Fio      => "fio",
+            Format::Blkparse => "blkparse",
+        }
+    }
+
+    /// The format of `text`: fio logs begin with a version line, anything else is taken to be
+    /// blkparse output.
+    pub fn detect(text: &str) -> Format {
+        match text.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) if line.trim().starts_with("fio version") => Format::Fio,
+            _ => Format::Blkparse,
+        }
+    }
+}
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        match s.trim().to_lowercase().as_str() {
+            "fio"      => Ok(Format::Fio),
+            "blkparse" => Ok(Format::Blkparse),
+            _ => Err(anyhow!("unknown IO log format '{s}', expected fio or blkparse")),
+        }
+    }
+}
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// A read or write found in an IO log, at a byte offset of the device or file it was made to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoggedIo {
+    pub operation: Operation,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// # Overview
+/// Reads the reads and writes of an IO log, in the order they were logged. Everything else a log
+/// holds (file management, syncs, trims, discards, flushes and IOs of no bytes) is skipped, as is
+/// the timing of fio version 3 logs.
+///
+/// fio logs name the file each IO was made to, but the offsets of every file are imported
+/// alike; see `map`.
+pub fn load(path: &Path) -> Result<Vec<LoggedIo>> {
+    let text: String = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("unable to read IO log {}: {e}", path.display()))?;
+    let format: Format = Format::detect(&text);
+    let ios: Vec<LoggedIo> = parse(&text, format).with_context(|| format!("invalid {format} IO log {}", path.display()))?;
+    if ios.is_empty() {
+        return Err(anyhow!("IO log {} holds no reads or writes; expected a fio iolog or blkparse output", path.display()));
+    }
+    Ok(ios)
+}
+
+/// Reads the reads and writes of `text`, a log in `format`.
+pub fn parse(text: &str, format: Format) -> Result<Vec<LoggedIo>> {
+    match format {
+        Format::Fio      => parse_fio(text),
+        Format::Blkparse => Ok(parse_blkparse(text)),
+    }
+}
+
+/// Maps each IO onto the extents of the constellation it covers, treating the files laid end to
+/// end as a single device so that offsets beyond its end wrap around. IOs are widened to whole
+/// pages, so that every page they touch can be verified, and split into extents of at most a
+/// chapter. Fails if nothing is left to do, as when the constellation holds no bytes.
+pub fn map(ios: &[LoggedIo], chapters: &ChapterMap) -> Result<Vec<(Extent, Operation)>> {
+    let extents: Vec<(Extent, Operation)> = ios.iter()
+                                               .flat_map(|io| chapters.extents_at(io.offset, io.length)
+                                                                      .into_iter()
+                                                                      .map(|extent| (extent, io.operation)))
+                                               .collect();
+    if extents.is_empty() {
+        return Err(anyhow!("none of the {} IOs of the log map onto the constellation, which holds {} bytes", ios.len(), chapters.bytes()));
+    }
+    Ok(extents)
+}
+
+/// Lines are `filename action [offset length]` in version 2, preceded by a timestamp in
+/// milliseconds in version 3.
+fn parse_fio(text: &str) -> Result<Vec<LoggedIo>> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let version: usize = match lines.next().map(|(_, line)| line.trim()) {
+        Some("fio version 2 iolog") => 2,
+        Some("fio version 3 iolog") => 3,
+        Some(line) => return Err(anyhow!("unsupported fio iolog version '{line}', expected version 2 or 3")),
+        None => return Ok(Vec::new()),
+    };
+
+    let mut ios: Vec<LoggedIo> = Vec::new();
+    for (index, line) in lines {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Skip the timestamp; filenames may hold no spaces in either version.
+        let fields: &[&str] = fields.get(version - 2..).unwrap_or_default();
+        let operation: Operation = match fields.get(1) {
+            Some(&"read")  => Operation::Read,
+            Some(&"write") => Operation::Write,
+            Some(&("add" | "open" | "close" | "sync" | "datasync" | "trim" | "wait")) => continue,
+            _ => return Err(anyhow!("line {}: unknown action in '{line}'", index + 1)),
+        };
+        let number = |field: usize| -> Result<u64> {
+            fields.get(field)
+                  .ok_or_else(|| anyhow!("line {}: missing offset or length in '{line}'", index + 1))?
+                  .parse::<u64>()
+                  .map_err(|e| anyhow!("line {}: invalid offset or length in '{line}': {e}", index + 1))
+        };
+        let (offset, length) = (number(2)?, number(3)?);
+        if length > 0 { ios.push(LoggedIo { operation, offset, length }); }
+    }
+    Ok(ios)
+}
+
+/// Lines of interest are
+///
+/// ```txt
+///    8,0    3        1     0.000000000   697  Q   W 223490 + 8 [kworker/u8:0]
+/// ```
+///
+/// that is the device, CPU, sequence number, time, process id, action, RWBS flags, first sector
+/// and number of sectors. Anything else, such as the summary blkparse ends with, is skipped.
+fn parse_blkparse(text: &str) -> Vec<LoggedIo> {
+    let events: Vec<(&str, LoggedIo)> = text.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 || fields[8] != "+" || !fields[0].contains(',') { return None; }
+        let operation: Operation = match fields[6] {
+            rwbs if rwbs.contains('R') => Operation::Read,
+            rwbs if rwbs.contains('W') => Operation::Write,
+            _ => return None,
+        };
+        let offset: u64 = fields[7].parse::<u64>().ok()?.checked_mul(SECTOR_BYTES)?;
+        let length: u64 = fields[9].parse::<u64>().ok()?.checked_mul(SECTOR_BYTES)?;
+        (length > 0).then_some((fields[5], LoggedIo { operation, offset, length }))
+    }).collect();
+
+    let Some(action) = BLKPARSE_ACTIONS.iter().find(|action| events.iter().any(|(a, _)| a == *action)) else {
+        return Vec::new();
+    };
+    events.into_iter().filter(|(a, _)| a == action).map(|(_, io)| io).collect()
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn logs_are_read_and_mapped_onto_pages() {
+        use crate::PAGE_BYTES;
+
+        let v2: &str = "fio version 2 iolog\n/dev/sdb add\n/dev/sdb open\n/dev/sdb write 0 4096\n/dev/sdb read 8192 512\n/dev/sdb sync\n/dev/sdb close\n";
+        let v3: &str = "fio version 3 iolog\n0 /dev/sdb add\n3 /dev/sdb open\n10 /dev/sdb write 0 4096\n12 /dev/sdb read 8192 512\n20 /dev/sdb close\n";
+        for log in [v2, v3] {
+            assert_eq!(Format::detect(log), Format::Fio);
+            assert_eq!(parse(log, Format::Fio).unwrap(), vec![
+                LoggedIo { operation: Operation::Write, offset: 0, length: 4096 },
+                LoggedIo { operation: Operation::Read, offset: 8192, length: 512 },
+            ]);
+        }
+        assert!(parse("fio version 1 iolog\n", Format::Fio).is_err());
+        assert!(parse("fio version 2 iolog\n/dev/sdb read 0\n", Format::Fio).is_err());
+        assert!(parse("fio version 2 iolog\n/dev/sdb erase 0 4096\n", Format::Fio).is_err());
+        assert_eq!(parse("fio version 2 iolog\n/dev/sdb read 0 0\n", Format::Fio).unwrap(), vec![]);
+
+        // Each request is taken once, when queued, and flushes and discards are skipped.
+        let blkparse: &str = "  8,16   1        1     0.000000000  4242  Q   W 16 + 8 [fio]\n  8,16   1        2     0.000001000  4242  D   W 16 + 8 [fio]\n  8,16   1        3     0.000050000     0  C   W 16 + 8 [0]\n  8,16   1        4     0.000060000  4242  Q  RA 1024 + 16 [fio]\n  8,16   1        5     0.000070000  4242  Q FWS 0 + 0 [fio]\n  8,16   1        6     0.000080000  4242  Q   D 64 + 8 [fio]\nCPU1 (8,16):\n Reads Queued:           1,        8KiB\n";
+        assert_eq!(Format::detect(blkparse), Format::Blkparse);
+        assert_eq!(parse(blkparse, Format::Blkparse).unwrap(), vec![
+            LoggedIo { operation: Operation::Write, offset: 16 * 512, length: 4096 },
+            LoggedIo { operation: Operation::Read, offset: 1024 * 512, length: 8192 },
+        ]);
+        let completions: String = blkparse.lines().filter(|line| !line.contains(" Q ")).collect::<Vec<_>>().join("\n");
+        assert_eq!(parse(&completions, Format::Blkparse).unwrap().len(), 1);
+
+        // Two files of three pages, the second's last page truncated, and chapters of two pages.
+        let page: u64 = PAGE_BYTES as u64;
+        let chapters = ChapterMap::new([3 * page, 2 * page + 100], 2 * page).unwrap();
+        let ios: Vec<LoggedIo> = vec![
+            LoggedIo { operation: Operation::Read, offset: 10, length: 20 },
+            LoggedIo { operation: Operation::Write, offset: 2 * page + 1, length: 3 * page },
+            LoggedIo { operation: Operation::Read, offset: 7 * page, length: page },
+        ];
+        assert_eq!(map(&ios, &chapters).unwrap(), vec![
+            (Extent { file: 0, offset: 0, length: page }, Operation::Read),
+            (Extent { file: 0, offset: 2 * page, length: page }, Operation::Write),
+            (Extent { file: 1, offset: 0, length: 2 * page }, Operation::Write),
+            (Extent { file: 1, offset: 2 * page, length: 100 }, Operation::Write),
+            (Extent { file: 0, offset: page, length: page }, Operation::Read),
+        ]);
+
+        // An IO longer than the constellation covers it once, and an empty one leaves no work.
+        let whole = LoggedIo { operation: Operation::Read, offset: 100, length: u64::MAX };
+        assert_eq!(map(&[whole], &chapters).unwrap().iter().map(|(extent, _)| extent.length).sum::<u64>(), chapters.bytes());
+        assert!(map(&ios, &ChapterMap::new([0, 0], 2 * page).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use serde::{de::{self, IgnoredAny}, Deserialize, Deserializer, Serialize};
+use anyhow::{anyhow, Context, Result};
+use super::{
+    affinity::{IdList, Placement},
+    constellation::{self, FileConstellation, FileOptions, Preallocation},
+    engine::Engine,
+    measure::WarmUp,
+    metadata::Directories,
+    placement::FilePlacement,
+    sizes::FileSizes,
+    throttle::{Limits, Rate},
+    workload::{Access, Durability, Mix},
+    PAGE_BYTES,
+    PAGE_COUNT,
+};
+
+
+/// Seed used to generate page data when none is given.
+pub const DEFAULT_SEED: u64 = 0xD7D6D5D4D3D2D1D0;
+
+/// # Overview
+/// A declarative description of an experiment: a constellation and the phases which run against
+/// it, in order. Jobs are written in TOML or, for files ending in `.json`, JSON. Values are
+/// written as they would be on the command line and every key has the command line's default.
+///
+/// ```toml
+/// [constellation]
+/// roots = ["/mnt/a", "/mnt/b"]
+/// directories = "directory:4"
+/// file-count = 64
+/// file-sizes = "lognormal:1MiB,1.0"
+///
+/// [[phases]]
+/// kind = "create"
+/// threads = 8
+///
+/// [[phases]]
+/// kind = "drop-caches"
+///
+/// [[phases]]
+/// kind = "read"
+/// name = "random-read"
+/// access = "random"
+/// duration = "60s"
+///
+/// [[phases]]
+/// kind = "mixed"
+/// mix = "70/30"
+/// rate-limit = "200MiB"
+/// durability = "end-of-phase"
+///
+/// [[phases]]
+/// kind = "verify"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Job {
+    #[serde(default)]
+    pub constellation: Layout,
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+}
+impl Job {
+    /// Reads and validates the job in `path`.
+    pub fn load(path: &Path) -> Result<Job> {
+        let contents: String = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read job file {}", path.display()))?;
+        let job: Job = match path.extension().is_some_and(|extension| extension == "json") {
+            true  => Job::from_json(&contents),
+            false => Job::from_toml(&contents),
+        }.with_context(|| format!("invalid job file {}", path.display()))?;
+        Ok(job)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Job> {
+        let job: Job = toml::from_str(s)?;
+        job.validate()?;
+        Ok(job)
+    }
+
+    pub fn from_json(s: &str) -> Result<Job> {
+        let job: Job = serde_json::from_str(s)?;
+        job.validate()?;
+        Ok(job)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.phases.is_empty() {
+            return Err(anyhow!("a job requires at least one phase"));
+        }
+        if let Some(position) = self.phases.iter().position(|phase| phase.kind == Kind::Destroy) {
+            if position + 1 != self.phases.len() {
+                return Err(anyhow!("phase {} destroys the constellation, so it must be the last phase", position + 1));
+            }
+        }
+        self.phases.iter()
+                   .enumerate()
+                   .try_for_each(|(index, phase)| {
+                       phase.validate().with_context(|| format!("invalid phase {} ({})", index + 1, phase.label()))
+                   })
+    }
+}
+
+/// The constellation a job runs against. See the layout arguments of the command line.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Layout {
+    pub roots: Vec<PathBuf>,
+    pub seed: u64,
+    /// Layers of directories, outermost first, as `prefix:fan-out,...`.
+    #[serde(deserialize_with = "layers")]
+    pub directories: Vec<(String, u64)>,
+    pub file_prefix: String,
+    /// Number of files in each innermost directory.
+    pub file_count: u64,
+    #[serde(deserialize_with = "parsed")]
+    pub file_sizes: FileSizes,
+    #[serde(deserialize_with = "parsed")]
+    pub file_placement: FilePlacement,
+    #[serde(deserialize_with = "parsed")]
+    pub preallocate: Preallocation,
+}
+impl Layout {
+    /// The constellation, which is not created. See `FileConstellation::describe`.
+    pub fn describe(&self) -> Result<FileConstellation> {
+        FileConstellation::describe(
+            self.roots.clone(),
+            self.directories.clone(),
+            (self.file_prefix.clone(), self.file_count),
+            self.file_sizes.clone().with_seed(self.seed),
+            FileOptions {
+                directo_io: false,
+                preallocation: self.preallocate.with_seed(self.seed),
+                placement: self.file_placement.clone().with_seed(self.seed),
+            }
+        )
+    }
+}
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            roots: vec![PathBuf::from("./")],
+            seed: DEFAULT_SEED,
+            directories: vec![("directory".to_string(), 1)],
+            file_prefix: "file".to_string(),
+            file_count: 1,
+            file_sizes: FileSizes::fixed((PAGE_COUNT * PAGE_BYTES) as u64).expect("a whole number of pages is a valid size"),
+            file_placement: FilePlacement::default(),
+            preallocate: Preallocation::default(),
+        }
+    }
+}
+
+/// What a phase does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    /// Creates the constellation, or resumes its creation, and writes every page.
+    Create,
+    /// Evicts the constellation's files from the page cache.
+    DropCaches,
+    /// Reads the constellation, as `Read` does, to bring caches and devices to a steady state.
+    WarmUp,
+    Read,
+    /// Reads and writes according to the phase's `mix`.
+    Mixed,
+    /// The metadata benchmark; see `metadata`.
+    Metadata,
+    /// Checks every page, single-threaded.
+    Verify,
+    /// Removes the constellation. Only the last phase may do so.
+    Destroy,
+}
+impl Kind {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Kind::Create     => "create",
+            Kind::DropCaches => "drop-caches",
+            Kind::WarmUp     => "warm-up",
+            Kind::Read       => "read",
+            Kind::Mixed      => "mixed",
+            Kind::Metadata   => "metadata",
+            Kind::Verify     => "verify",
+            Kind::Destroy    => "destroy",
+        }
+    }
+
+    /// Whether the phase runs on a pool of workers, which `Workers` configures.
+    pub fn has_workers(&self) -> bool {
+        matches!(self, Kind::Create | Kind::WarmUp | Kind::Read | Kind::Mixed | Kind::Metadata)
+    }
+}
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// A single step of a job.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Phase {
+    pub kind: Kind,
+    /// Label printed with the phase's results. Defaults to the kind.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Read/write ratio of a mixed phase, e.g. `70/30`.
+    #[serde(default, deserialize_with = "parsed_option")]
+    pub mix: Option<Mix>,
+    /// Number of files each thread of a metadata phase works on.
+    #[serde(default)]
+    pub metadata_files: Option<u64>,
+    #[serde(default, deserialize_with = "parsed_option")]
+    pub metadata_directories: Option<Directories>,
+    #[serde(flatten)]
+    pub workers: Workers,
+    /// Keys which no field accepts. They are rejected rather than ignored so that a misspelt
+    /// option can't silently change an experiment.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, IgnoredAny>,
+}
+impl Phase {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.kind.to_str())
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(key) = self.unknown.keys().next() {
+            return Err(anyhow!("unknown key '{key}'"));
+        }
+        if self.mix.is_some() != (self.kind == Kind::Mixed) {
+            return Err(anyhow!("'mix' must be given for, and only for, mixed phases"));
+        }
+        if (self.metadata_files.is_some() || self.metadata_directories.is_some()) && self.kind != Kind::Metadata {
+            return Err(anyhow!("'metadata-files' and 'metadata-directories' only apply to metadata phases"));
+        }
+        if self.metadata_files == Some(0) {
+            return Err(anyhow!("'metadata-files' must be at least 1"));
+        }
+        if !self.kind.has_workers() && self.workers != Workers::default() {
+            return Err(anyhow!("a {} phase doesn't run workers, so it takes no worker options", self.kind));
+        }
+        if self.workers.duration.is_some() && !matches!(self.kind, Kind::WarmUp | Kind::Read | Kind::Mixed) {
+            return Err(anyhow!("only warm-up, read and mixed phases may be timed"));
+        }
+        self.workers.validate()
+    }
+}
+
+/// How the workers of a phase issue IO. Each field matches the command line flag of the same
+/// name and has the same default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Workers {
+    /// Number of threads. Defaults to the available parallelism.
+    pub threads: Option<usize>,
+    #[serde(deserialize_with = "parsed_option")]
+    pub cpus: Option<IdList>,
+    #[serde(deserialize_with = "parsed_option")]
+    pub numa_nodes: Option<IdList>,
+    #[serde(deserialize_with = "parsed")]
+    pub engine: Engine,
+    #[serde(deserialize_with = "parsed")]
+    pub access: Access,
+    #[serde(deserialize_with = "parsed")]
+    pub durability: Durability,
+    #[serde(deserialize_with = "parsed_option")]
+    pub rate_limit: Option<Rate>,
+    #[serde(deserialize_with = "parsed_option")]
+    pub thread_rate_limit: Option<Rate>,
+    #[serde(deserialize_with = "duration")]
+    pub rate_burst: Duration,
+    #[serde(deserialize_with = "parsed")]
+    pub warm_up: WarmUp,
+    #[serde(deserialize_with = "duration")]
+    pub ramp_up: Duration,
+    /// When given, workers pass over the constellation repeatedly until this much time has
+    /// passed, rather than once.
+    #[serde(deserialize_with = "duration_option")]
+    pub duration: Option<Duration>,
+    /// Number of files each thread keeps open. Defaults to an even share of the open file limit.
+    pub handle_cache: Option<u64>,
+    pub open_per_op: bool,
+}
+impl Workers {
+    pub fn limits(&self) -> Limits {
+        Limits { global: self.rate_limit, thread: self.thread_rate_limit, burst: self.rate_burst }
+    }
+
+    pub fn placement(&self) -> Placement {
+        match (&self.cpus, &self.numa_nodes) {
+            (Some(cpus), _)  => Placement::Cpus(cpus.clone()),
+            (_, Some(nodes)) => Placement::Nodes(nodes.clone()),
+            _                => Placement::None,
+        }
+    }
+
+    /// Checks the combinations of options which the command line rejects while parsing.
+    pub fn validate(&self) -> Result<()> {
+        if self.threads == Some(0) {
+            return Err(anyhow!("'threads' must be at least 1"));
+        }
+        if self.cpus.is_some() && self.numa_nodes.is_some() {
+            return Err(anyhow!("'cpus' and 'numa-nodes' can't be used together"));
+        }
+        if self.handle_cache == Some(0) {
+            return Err(anyhow!("'handle-cache' must be at least 1"));
+        }
+        if self.handle_cache.is_some() && self.open_per_op {
+            return Err(anyhow!("'handle-cache' and 'open-per-op' can't be used together"));
+        }
+        if self.duration.is_some_and(|duration| duration.is_zero()) {
+            return Err(anyhow!("'duration' must be greater than zero"));
+        }
+        Ok(())
+    }
+}
+impl Default for Workers {
+    fn default() -> Workers {
+        Workers {
+            threads: None,
+            cpus: None,
+            numa_nodes: None,
+            engine: Engine::default(),
+            access: Access::default(),
+            durability: Durability::default(),
+            rate_limit: None,
+            thread_rate_limit: None,
+            rate_burst: Duration::ZERO,
+            warm_up: WarmUp::None,
+            ramp_up: Duration::ZERO,
+            duration: None,
+            handle_cache: None,
+            open_per_op: false,
+        }
+    }
+}
+
+/// Deserializes a value from the string accepted for it on the command line.
+fn parsed<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where D: Deserializer<'de>, T: FromStr<Err = anyhow::Error> {
+    let s: String = String::deserialize(deserializer)?;
+    s.parse::<T>().map_err(|e| de::Error::custom(format!("{e:#}")))
+}
+
+fn parsed_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where D: Deserializer<'de>, T: FromStr<Err = anyhow::Error> {
+    parsed(deserializer).map(Some)
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let s: String = String::deserialize(deserializer)?;
+    humantime::parse_duration(&s).map_err(|e| de::Error::custom(format!("invalid duration '{s}': {e}")))
+}
+
+fn duration_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
+
+fn layers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(String, u64)>, D::Error> {
+    let s: String = String::deserialize(deserializer)?;
+    constellation::parse_layers(&s).map_err(|e| de::Error::custom(format!("{e:#}")))
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn jobs_parse_and_reject_invalid_phases() {
+        let job: Job = Job::from_toml(r#"
+            [constellation]
+            roots = ["/tmp"]
+            directories = "a:2,b:4"
+            file-count = 3
+            file-sizes = "uniform:4KiB-1MiB"
+
+            [[phases]]
+            kind = "create"
+            threads = 2
+            durability = "every-write"
+
+            [[phases]]
+            kind = "drop-caches"
+
+            [[phases]]
+            kind = "read"
+            name = "random-read"
+            access = "random"
+            duration = "30s"
+            rate-limit = "100MiB"
+
+            [[phases]]
+            kind = "mixed"
+            mix = "70/30"
+
+            [[phases]]
+            kind = "verify"
+        "#).unwrap();
+
+        assert_eq!(job.constellation.directories, vec![("a".to_string(), 2), ("b".to_string(), 4)]);
+        assert_eq!(job.constellation.file_prefix, "file");
+        let kinds: Vec<Kind> = job.phases.iter().map(|phase| phase.kind).collect();
+        assert_eq!(kinds, [Kind::Create, Kind::DropCaches, Kind::Read, Kind::Mixed, Kind::Verify]);
+        assert_eq!(job.phases[0].workers.durability, Durability::EveryWrite);
+        assert_eq!(job.phases[2].label(), "random-read");
+        assert_eq!(job.phases[2].workers.duration, Some(Duration::from_secs(30)));
+        assert!(job.phases[2].workers.limits().is_limited());
+        assert_eq!(job.phases[3].mix.map(|mix| mix.read_percent()), Some(70));
+        assert_eq!(job.phases[4].workers, Workers::default());
+
+        // JSON describes the same job.
+        let json: Job = Job::from_json(r#"{ "phases": [ { "kind": "read", "access": "random" } ] }"#).unwrap();
+        assert_eq!(json.phases[0].workers.access, Access::Random);
+        assert_eq!(json.constellation.seed, DEFAULT_SEED);
+
+        let invalid = |phases: &str| Job::from_toml(&format!("[[phases]]\n{phases}")).is_err();
+        assert!(invalid("kind = \"read\"\nthreds = 4"), "misspelt keys are rejected");
+        assert!(invalid("kind = \"mixed\""), "mixed phases need a mix");
+        assert!(invalid("kind = \"read\"\nmix = \"50/50\""));
+        assert!(invalid("kind = \"verify\"\nthreads = 4"), "verify doesn't run workers");
+        assert!(invalid("kind = \"create\"\nduration = \"1m\""), "creation can't be timed");
+        assert!(invalid("kind = \"read\"\naccess = \"strided\""));
+        assert!(invalid("kind = \"destroy\"\n[[phases]]\nkind = \"read\""), "destroy must be last");
+        assert!(Job::from_toml("[constellation]\nroot = \"/tmp\"").is_err());
+        assert!(Job::from_toml("").is_err(), "a job needs phases");
+    }
+}
@@ -6,6 +6,22 @@ pub mod page;
 pub mod chapter;
 pub mod constellation;
 pub mod queue;
+pub mod measure;
+pub mod throttle;
+pub mod workload;
+pub mod affinity;
+pub mod engine;
+pub mod metadata;
+pub mod sizes;
+pub mod placement;
+pub mod job;
+pub mod sweep;
+pub mod progress;
+pub mod logging;
+pub mod series;
+pub mod histogram;
+pub mod trace;
+pub mod iolog;
 
 /// Size of a Page in Bytes
 pub const PAGE_BYTES: usize         = 4096 /*bytes*/;
@@ -0,0 +1,75 @@
+use std::{path::Path, time::SystemTime};
+use anyhow::{anyhow, Result};
+use log::LevelFilter;
+
+
+/// Target of reports of data which isn't what it should be: invalid pages, short reads and
+/// files of the wrong length. They are logged at `warn` or above.
+///
+/// ```
+/// log::warn!(target: super_massive_io::logging::CORRUPTION, "file 3, page 12 is invalid");
+/// ```
+pub const CORRUPTION: &str = "corruption";
+
+/// # Overview
+/// Installs the logger. Every record at `level` or above is written to `file`, or to stderr
+/// when none is given, as:
+///
+/// ```txt
+///    2024-05-01T12:00:00.000000Z WARN  [worker-3] corruption: invalid page 12 of file 3 ...
+/// ```
+///
+/// Corruption reports are also written to `corruption`, when given, whatever `level` is, so that
+/// they are kept even when everything else is silenced. Both files are appended to.
+pub fn init(level: LevelFilter, file: Option<&Path>, corruption: Option<&Path>) -> Result<()> {
+    let mut output = fern::Dispatch::new().level(level);
+    output = match file {
+        Some(path) => output.chain(fern::log_file(path).map_err(|e| anyhow!("unable to open log file {}: {e}", path.display()))?),
+        None => output.chain(std::io::stderr()),
+    };
+
+    let mut logger = fern::Dispatch::new()
+        .format(|out, message, record| {
+            let thread = std::thread::current();
+            out.finish(format_args!("{} {:<5} [{}] {}: {}",
+                                    humantime::format_rfc3339_micros(SystemTime::now()),
+                                    record.level(),
+                                    thread.name().unwrap_or("unnamed"),
+                                    record.target(),
+                                    message))
+        })
+        .chain(output);
+    if let Some(path) = corruption {
+        logger = logger.chain(
+            fern::Dispatch::new().filter(|metadata| metadata.target() == CORRUPTION)
+                                 .chain(fern::log_file(path).map_err(|e| anyhow!("unable to open corruption log {}: {e}", path.display()))?)
+        );
+    }
+    logger.apply().map_err(|e| anyhow!("unable to install the logger: {e}"))
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn corruption_is_kept_when_other_records_are_not() {
+        use log::{info, warn};
+        use tempfile::tempdir;
+
+        let directory = tempdir().expect("crate should be able to create temporary directories");
+        let (file, corruption) = (directory.path().join("smio.log"), directory.path().join("corruption.log"));
+        init(LevelFilter::Error, Some(&file), Some(&corruption)).unwrap();
+        assert!(init(LevelFilter::Error, None, None).is_err(), "the logger is installed once");
+
+        info!("not logged");
+        warn!(target: CORRUPTION, "invalid page 12 of file 3");
+        log::logger().flush();
+
+        assert!(std::fs::read_to_string(&file).unwrap().is_empty());
+        let reports: String = std::fs::read_to_string(&corruption).unwrap();
+        assert_eq!(reports.lines().count(), 1);
+        assert!(reports.contains("WARN ") && reports.ends_with("corruption: invalid page 12 of file 3\n"));
+    }
+}
@@ -6,9 +6,10 @@ use std::{
         SeekFrom, 
         Write 
     }, 
-    path::PathBuf, 
+    path::{Path, PathBuf}, 
+    process::ExitCode,
     sync::Arc, 
-    time::{ Duration, SystemTime }
+    time::{ Duration, Instant, SystemTime }
 };
 use clap::{
     value_parser, 
@@ -19,9 +20,9 @@ use clap::{
     Command,
     ValueHint
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use log::{/*info,debug,*/warn};
+use log::{/*info,debug,*/warn, LevelFilter};
 
 use indicatif::{HumanBytes, HumanDuration};
 use rayon::{
@@ -32,10 +33,26 @@ use rayon::{
     ThreadPool
 };
 use super_massive_io::{
-    constellation::{FileConstellation, FileOptions},
+    constellation::{self, FileConstellation, FileOptions, HandleCache, Handles, PageAddress, Preallocation, Survey},
     chapter::Chapter,
     page::Page,
     queue::work::DIter,
+    measure::{Summary, Tally, WarmUp, Window},
+    throttle::{Limits, Rate, Throttle, TokenBucket},
+    workload::{Access, ChapterMap, Durability, Extent, Mix, Operation},
+    affinity::{IdList, Location, Placement},
+    engine::Engine,
+    metadata::{self, Directories, MetadataLayout},
+    sizes::{self, FileSizes},
+    placement::FilePlacement,
+    progress::Progress,
+    series::{Sampler, Series},
+    histogram::{Histogram, Histograms, Recorded},
+    trace::{self, Timing, Tracer},
+    iolog::{self, LoggedIo},
+    logging::{self, CORRUPTION},
+    job::{self, Job, Workers},
+    sweep::{Axis, Combination, Sweep, Table},
     //Inspector, 
     PAGES_PER_CHAPTER, 
     PAGE_BYTES
@@ -47,361 +64,1270 @@ fn cli_arguments() -> Command {
         .about("Research application into File System IO")
         .version("0.1.0")
         .author("defghij")
-        // Common Arguments
+        .subcommand_required(true)
+        .arg_required_else_help(true)
         .arg(
-            Arg::new("create")
-                .long("create")
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
                 .action(ArgAction::SetTrue)
-                .conflicts_with("config")
-                .help("Enables the creation of data.")
+                .help("Show the progress of each thread as well as the total.")
         )
         .arg(
-            Arg::new("bench")
-                .long("bench")
-                .action(ArgAction::SetTrue)
-                .help("Enables the benchmarking mode. Requires '--configuration-file' if not used with 'create' flag")
-        )
-        .arg(
-            Arg::new("seed")
-                .short('s')
-                .long("seed")
-                .default_value("15552853473234178512") /*0xD7D6D5D4D3D2D1D0*/
-                .value_parser(value_parser!(u64))
-                .value_name("integer")
+            Arg::new("log-level")
+                .long("log-level")
+                .global(true)
+                .default_value("warn")
+                .value_parser(|s: &str| s.parse::<LevelFilter>())
+                .value_name("level")
                 .value_hint(ValueHint::Other)
-                .group("creation")
-                .help("Seed value used to generate page data.")
-         )
+                .help("Least severe records logged: off, error, warn, info, debug or trace.")
+        )
         .arg(
-            Arg::new("config")
-                .long("configuration-file")
+            Arg::new("log-file")
+                .long("log-file")
+                .global(true)
                 .value_parser(value_parser!(PathBuf))
                 .value_name("path")
                 .value_hint(ValueHint::FilePath)
-                .group("benchmarking")
-                .help("Path to file that can be used in place of CLI arguments. Note: CLI arguments have precedence.")
+                .help("Append log records to this file rather than writing them to stderr.")
         )
         .arg(
-            Arg::new("disble-teardown")
-                .long("disable-teardown")
-                .action(ArgAction::SetTrue)
-                .groups(["creation", "benchmarking"])
-                .help("Will disable the deletion of test data after completion of the process.")
+            Arg::new("corruption-log")
+                .long("corruption-log")
+                .global(true)
+                .value_parser(value_parser!(PathBuf))
+                .value_name("path")
+                .value_hint(ValueHint::FilePath)
+                .help("Also append every report of invalid data to this file, whatever the log level.")
         )
-
-        // FILE LAYOUT
-        // Size, layout, count
-        .arg(
-            Arg::new("page-size")
-                .short('P')
-                .long("page-size")
-                .default_value("4096")
-                .value_parser(value_parser!(usize))
-                .value_name("integer")
-                .value_hint(ValueHint::Other)
-                .conflicts_with("config")
-                .help("The number of bytes a page must contain.")
+        .after_help("Exit status is 0 on success, 1 on failure, 2 for invalid arguments and 3 when verify, inspect, report or a job's verify phase find that the constellation on disk differs from its description.")
+        .subcommand(
+            Command::new("create")
+                .about("Create a constellation and write every page of its files. The constellation is kept until 'destroy'.")
+                .args(layout_arguments())
+                .args(worker_arguments())
+                .args(sampling_arguments())
+                .arg(
+                    Arg::new("preallocate")
+                        .long("preallocate")
+                        .default_value("sparse")
+                        .value_parser(|s: &str| s.parse::<Preallocation>())
+                        .value_name("strategy")
+                        .value_hint(ValueHint::Other)
+                        .help("How file blocks are allocated when files are created: sparse, fallocate, fallocate-keep-size, zero, pages (valid page data for the seed) or punch-hole (allocated, then deallocated).")
+                )
         )
-        .arg(
-            Arg::new("page-count")
-                .short('p')
-                .long("page-count")
-                .default_value("512")
-                .value_parser(value_parser!(u64))
-                .value_name("integer")
-                .value_hint(ValueHint::Other)
-                .conflicts_with("config")
-                .help("Size of a page as specified by $2^{exponent}$ bytes.")
+        .subcommand(
+            Command::new("bench")
+                .about("Benchmark an existing constellation. Files are read unless '--mixed' or '--metadata' is given.")
+                .args(layout_arguments())
+                .args(worker_arguments())
+                .args(sampling_arguments())
+                .args(bench_arguments())
         )
-        .arg(
-            Arg::new("file-size")
-                .short('F')
-                .long("file-size")
-                .default_value("2097152")
-                .value_parser(value_parser!(usize))
-                .value_name("integer")
-                .value_hint(ValueHint::Other)
-                .conflicts_with_all(["page-size", "page-count", "config"])
-                .help("Size of files as specified by $2^{exponent}$ bytes. If not a multiple of the page size, the remaining bytes will be be dropped")
+        .subcommand(
+            Command::new("sweep")
+                .about("Benchmark an existing constellation once for each combination of swept worker settings and print the results as one table.")
+                .args(layout_arguments())
+                .args(worker_arguments())
+                .args(sampling_arguments())
+                .args(bench_arguments())
+                .arg(
+                    Arg::new("vary")
+                        .long("vary")
+                        .required(true)
+                        .action(ArgAction::Append)
+                        .value_parser(|s: &str| s.parse::<Axis>())
+                        .value_name("parameter=values")
+                        .value_hint(ValueHint::Other)
+                        .help("A setting to sweep and its values, e.g. 'threads=1..16*2' or 'access=sequential,random'. May be given once per parameter of: threads, engine, access, durability, rate-limit, thread-rate-limit and handle-cache. Other settings are taken from their flags.")
+                )
+                .arg(
+                    Arg::new("combine")
+                        .long("combine")
+                        .default_value("product")
+                        .value_parser(|s: &str| s.parse::<Combination>())
+                        .value_name("product|zip")
+                        .value_hint(ValueHint::Other)
+                        .help("Run every combination of values, or pair the n-th values of each parameter.")
+                )
+                .arg(
+                    Arg::new("points")
+                        .long("points")
+                        .value_parser(|s: &str| s.parse::<IdList>())
+                        .value_name("list")
+                        .value_hint(ValueHint::Other)
+                        .help("Run only these points of the sweep, numbered from 0 in the order they would otherwise run, e.g. '0-3,7'.")
+                )
         )
-        .arg(
-            Arg::new("file-count")
-                .short('f')
-                .long("file-count")
-                .default_value("1")
-                .value_parser(value_parser!(u64))
-                .value_name("integer")
-                .value_hint(ValueHint::Other)
-                .conflicts_with("config")
-                .help("Number of files to create. Must be equal to or greater than the number of directories.")
+        .subcommand(
+            Command::new("verify")
+                .about("Check every page of an existing constellation using a slow, single-threaded, but sound reader.")
+                .args(layout_arguments())
         )
-        .arg(
-            Arg::new("file-prefix")
-                .long("file-prefix")
-                .default_value("file")
-                .value_parser(value_parser!(String))
-                .value_name("string")
-                .value_hint(ValueHint::Other)
-                .conflicts_with("config")
-                .help("Prefix for generated files (files). Will have form 'prefix##'")
+        .subcommand(
+            Command::new("inspect")
+                .about("Print the expected and on-disk page holding a byte of an existing constellation")
+                .args(layout_arguments())
+                .arg(
+                    Arg::new("path")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("Path of a file of the constellation")
+                )
+                .arg(
+                    Arg::new("offset")
+                        .required(true)
+                        .value_parser(sizes::parse_bytes)
+                        .value_name("bytes")
+                        .help("Offset of the byte within the file, e.g. '1MiB'")
+                )
         )
-
-        // DIRECTORY LAYOUT
-        //// Specify prefix and count
-        .arg(
-            Arg::new("directory-count")
-                .short('d')
-                .long("directory-count")
-                .default_value("1")
-                .value_parser(value_parser!(u64))
-                .value_name("integer")
-                .value_hint(ValueHint::Other)
-                .conflicts_with("config")
-                .help("Number of generated directories. Must be equal or greater than the number of roots.")
+        .subcommand(
+            Command::new("run")
+                .about("Run the phases of a job file, in order, against the constellation it describes.")
+                .arg(
+                    Arg::new("job")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("Path of a TOML job file, or a JSON one if it ends in '.json'")
+                )
+                .args(sampling_arguments())
         )
-        .arg(
-            Arg::new("directory-prefix")
-                .long("directory-prefix")
-                .default_value("directory")
-                .value_parser(value_parser!(String))
-                .value_name("string")
-                .value_hint(ValueHint::Other)
-                .conflicts_with("config")
-                .help("Prefix for generated directories. Will have the form 'prefix##'")
+        .subcommand(
+            Command::new("replay")
+                .about("Re-issue the reads, writes and syncs of a trace against an existing constellation, one thread per thread of the trace.")
+                .args(layout_arguments())
+                .arg(
+                    Arg::new("trace")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("Path of a binary trace captured with '--trace', or of a text trace with lines of 'time_us thread operation file offset length'")
+                )
+                .arg(
+                    Arg::new("timing")
+                        .long("timing")
+                        .default_value("original")
+                        .value_parser(|s: &str| s.parse::<Timing>())
+                        .value_name("original|fast")
+                        .value_hint(ValueHint::Other)
+                        .help("Issue each IO at its time in the trace, or as soon as the previous IO of its thread completes.")
+                )
         )
-        .arg(
-            Arg::new("roots")
-                .long("roots")
-                .default_value("./")
-                .value_delimiter(',')
-                .value_parser(value_parser!(PathBuf))
-                .value_name("path")
-                .value_hint(ValueHint::FilePath)
-                .conflicts_with("config")
-                .help("Path(s) which will contain the directories and files")
+        .subcommand(
+            Command::new("latency")
+                .about("Merge latency histograms saved with '--histograms', from any number of runs, and print the percentiles of each operation.")
+                .arg(
+                    Arg::new("histograms")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(value_parser!(PathBuf))
+                        .value_hint(ValueHint::FilePath)
+                        .help("Paths of saved histograms")
+                )
+                .arg(
+                    Arg::new("by-phase")
+                        .long("by-phase")
+                        .action(ArgAction::SetTrue)
+                        .help("Merge only histograms of phases with the same name, rather than every phase.")
+                )
         )
-
-        // Write Characterization
-        .arg(
-            Arg::new("engine")
-                .long("engine")
-                .value_parser(["posix", "direct_io", "mmap", "libaio", "io_uring"])
-                .help("Select the file IO interface to use.")
+        .subcommand(
+            Command::new("destroy")
+                .about("Remove the files and directories of a constellation. Those already removed are skipped.")
+                .args(layout_arguments())
         )
-        
-        // TODO: Temporary
-        .arg(
-            Arg::new("verify")
-                .long("verify")
-                .action(ArgAction::SetTrue)
-                .help("Use slow, single-threaded, but sound read/verify function to check writes.")
+        .subcommand(
+            Command::new("report")
+                .about("Describe a constellation and check that its files exist with their expected lengths.")
+                .args(layout_arguments())
         )
 } 
 
+/// Arguments which keep measurements beyond the printed summaries: throughput and latency of data
+/// phases over time, latency histograms and a trace of every IO.
+fn sampling_arguments() -> Vec<Arg> {
+    vec![
+        Arg::new("samples")
+            .long("samples")
+            .value_parser(value_parser!(PathBuf))
+            .value_name("path")
+            .value_hint(ValueHint::FilePath)
+            .help("Sample the throughput and latency of each operation while data moves and save them to this file: a JSON array if it ends in '.json', otherwise CSV. Every sample is labelled with its phase."),
+        Arg::new("sample-interval")
+            .long("sample-interval")
+            .default_value("1s")
+            .value_parser(humantime::parse_duration)
+            .value_name("duration")
+            .value_hint(ValueHint::Other)
+            .requires("samples")
+            .help("Time between samples, e.g. '100ms'."),
+        Arg::new("histograms")
+            .long("histograms")
+            .value_parser(value_parser!(PathBuf))
+            .value_name("path")
+            .value_hint(ValueHint::FilePath)
+            .help("Save the latency histogram of each operation of each phase to this JSON file. Histograms of several runs can be merged with 'latency'."),
+        Arg::new("trace")
+            .long("trace")
+            .value_parser(value_parser!(PathBuf))
+            .value_name("path")
+            .value_hint(ValueHint::FilePath)
+            .help("Record every read, write and sync, with its time, thread, file, offset, length, latency and result, to this binary trace. Traces can be re-issued with 'replay'."),
+    ]
+}
+
+/// Arguments which choose the workload of a benchmark and how long it runs.
+fn bench_arguments() -> Vec<Arg> {
+    vec![
+        Arg::new("duration")
+            .long("duration")
+            .value_parser(humantime::parse_duration)
+            .value_name("duration")
+            .value_hint(ValueHint::Other)
+            .conflicts_with("metadata")
+            .help("Pass over the constellation repeatedly until this much time has passed, rather than once."),
+        Arg::new("mixed")
+            .long("mixed")
+            .value_parser(|s: &str| s.parse::<Mix>())
+            .value_name("read/write")
+            .value_hint(ValueHint::Other)
+            .conflicts_with("metadata")
+            .help("Run a mixed benchmark in which each chapter is read or written according to the given ratio, e.g. '70/30'. Writes mutate the pages already on disk."),
+        Arg::new("metadata")
+            .long("metadata")
+            .action(ArgAction::SetTrue)
+            .help("Run the metadata benchmark: each thread creates, stats, opens and closes, renames and then unlinks its own empty files within the constellation's directories. Each operation is timed separately."),
+        Arg::new("metadata-files")
+            .long("metadata-files")
+            .default_value("1024")
+            .value_parser(value_parser!(u64).range(1..))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .requires("metadata")
+            .help("Number of files each thread works on in the metadata benchmark."),
+        Arg::new("metadata-directories")
+            .long("metadata-directories")
+            .default_value("shared")
+            .value_parser(|s: &str| s.parse::<Directories>())
+            .value_name("shared|unique")
+            .value_hint(ValueHint::Other)
+            .requires("metadata")
+            .help("Whether threads of the metadata benchmark share the constellation's directories or each work in a directory of their own."),
+        Arg::new("io-log")
+            .long("io-log")
+            .value_parser(value_parser!(PathBuf))
+            .value_name("path")
+            .value_hint(ValueHint::FilePath)
+            .conflicts_with_all(["mixed", "metadata"])
+            .help("Make the reads and writes of a fio iolog (version 2 or 3) or of blkparse output, in place of passing over the constellation. Offsets are mapped onto the pages of the files laid end to end, wrapping around past the last; every page read is verified and writes mutate the pages they cover. The log's timing is not kept, and its IOs are issued in order unless access is random."),
+    ]
+}
+
+/// Arguments which describe a constellation. Every subcommand takes them so that each can find
+/// the files which `create` made.
+fn layout_arguments() -> Vec<Arg> {
+    vec![
+        Arg::new("seed")
+            .short('s')
+            .long("seed")
+            .default_value("15552853473234178512") /*0xD7D6D5D4D3D2D1D0*/
+            .value_parser(value_parser!(u64))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .help("Seed value used to generate page data."),
+
+        // FILE LAYOUT
+        // Size, layout, count
+        Arg::new("directory-layers")
+            .long("directory-layers")
+            .value_parser(|s: &str| constellation::parse_layers(s))
+            .value_name("prefix:fan-out,...")
+            .value_hint(ValueHint::Other)
+            .conflicts_with("directory-count")
+            .help("Nest directories in layers, outermost first, each with a prefix and the number of directories within each parent, e.g. 'a:4,b:16'. Files are placed in the innermost layer."),
+        Arg::new("file-placement")
+            .long("file-placement")
+            .default_value("round-robin")
+            .value_parser(|s: &str| s.parse::<FilePlacement>())
+            .value_name("policy")
+            .value_hint(ValueHint::Other)
+            .help("How files are assigned to directories and roots: round-robin, contiguous, hashed, 'weighted:<w0>,<w1>,...' (one weight per root) or 'mapping:<path>' (a file listing the root of each file)."),
+        Arg::new("file-sizes")
+            .long("file-sizes")
+            .value_parser(|s: &str| s.parse::<FileSizes>())
+            .value_name("distribution")
+            .value_hint(ValueHint::Other)
+            .help("Distribution of file sizes, derived from the seed and file id: a size such as '4MiB', 'uniform:<min>-<max>', 'lognormal:<median>,<sigma>[,<max>]' (at most 1TiB unless a maximum is given) or 'histogram:<size>=<weight>,...'. Overrides '--page-count'."),
+        Arg::new("page-size")
+            .short('P')
+            .long("page-size")
+            .default_value("4096")
+            .value_parser(value_parser!(usize))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .help("The number of bytes a page must contain. Pages are a fixed size, chosen when the application is built, which this must match."),
+        Arg::new("page-count")
+            .short('p')
+            .long("page-count")
+            .default_value("512")
+            .value_parser(value_parser!(u64).range(1..))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .help("Number of pages in each file."),
+        Arg::new("file-size")
+            .short('F')
+            .long("file-size")
+            .value_parser(sizes::parse_bytes)
+            .value_name("bytes")
+            .value_hint(ValueHint::Other)
+            .conflicts_with_all(["page-count", "file-sizes"])
+            .help("Size of each file, e.g. '2MiB'. Must be a multiple of the page size; see '--file-sizes' for other sizes. Overrides '--page-count'."),
+        Arg::new("file-count")
+            .short('f')
+            .long("file-count")
+            .default_value("1")
+            .value_parser(value_parser!(u64))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .help("Number of files in each directory."),
+        Arg::new("file-prefix")
+            .long("file-prefix")
+            .default_value("file")
+            .value_parser(value_parser!(String))
+            .value_name("string")
+            .value_hint(ValueHint::Other)
+            .help("Prefix for generated files (files). Will have form 'prefix##'"),
+
+        // DIRECTORY LAYOUT
+        //// Specify prefix and count
+        Arg::new("directory-count")
+            .short('d')
+            .long("directory-count")
+            .default_value("1")
+            .value_parser(value_parser!(u64))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .help("Number of generated directories in each root."),
+        Arg::new("directory-prefix")
+            .long("directory-prefix")
+            .default_value("directory")
+            .value_parser(value_parser!(String))
+            .value_name("string")
+            .value_hint(ValueHint::Other)
+            .conflicts_with("directory-layers")
+            .help("Prefix for generated directories. Will have the form 'prefix##'"),
+        Arg::new("roots")
+            .long("roots")
+            .default_value("./")
+            .value_delimiter(',')
+            .value_parser(value_parser!(PathBuf))
+            .value_name("path")
+            .value_hint(ValueHint::FilePath)
+            .help("Path(s) which will contain the directories and files"),
+    ]
+}
+
+/// Arguments of the subcommands which run IO on a pool of worker threads.
+fn worker_arguments() -> Vec<Arg> {
+    vec![
+        Arg::new("warm-up")
+            .long("warm-up")
+            .default_value("none")
+            .value_parser(|s: &str| s.parse::<WarmUp>())
+            .value_name("bytes|duration")
+            .value_hint(ValueHint::Other)
+            .help("IO excluded from measurement at the start of each mode. Either a byte count across all threads or a duration such as '30s'."),
+        Arg::new("ramp-up")
+            .long("ramp-up")
+            .default_value("0s")
+            .value_parser(humantime::parse_duration)
+            .value_name("duration")
+            .value_hint(ValueHint::Other)
+            .help("Duration over which threads are started, evenly spaced. IO is not measured until all threads are running."),
+        Arg::new("rate-limit")
+            .long("rate-limit")
+            .value_parser(|s: &str| s.parse::<Rate>())
+            .value_name("rate")
+            .value_hint(ValueHint::Other)
+            .help("Limit on all threads combined, either in bytes per second ('100MiB') or operations per second ('500iops'). Metadata operations move no bytes, so the metadata benchmark only accepts operations per second."),
+        Arg::new("thread-rate-limit")
+            .long("thread-rate-limit")
+            .value_parser(|s: &str| s.parse::<Rate>())
+            .value_name("rate")
+            .value_hint(ValueHint::Other)
+            .help("Limit on each thread, either in bytes per second ('100MiB') or operations per second ('500iops'). Metadata operations move no bytes, so the metadata benchmark only accepts operations per second."),
+        Arg::new("rate-burst")
+            .long("rate-burst")
+            .default_value("0s")
+            .value_parser(humantime::parse_duration)
+            .value_name("duration")
+            .value_hint(ValueHint::Other)
+            .help("IO which may be issued at once by a rate limit, given as time at the limited rate."),
+
+        // Thread Placement
+        Arg::new("threads")
+            .short('t')
+            .long("threads")
+            .value_parser(value_parser!(u32).range(1..))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .help("Number of worker threads. Defaults to the available parallelism."),
+        Arg::new("cpus")
+            .long("cpus")
+            .value_parser(|s: &str| s.parse::<IdList>())
+            .value_name("list")
+            .value_hint(ValueHint::Other)
+            .conflicts_with("numa-nodes")
+            .help("Pin each thread to one CPU from this list, e.g. '0-3,8'. Buffers are bound to the CPU's NUMA node."),
+        Arg::new("numa-nodes")
+            .long("numa-nodes")
+            .value_parser(|s: &str| s.parse::<IdList>())
+            .value_name("list")
+            .value_hint(ValueHint::Other)
+            .help("Assign threads round-robin to these NUMA nodes, pinning them to the node's CPUs and binding their buffers to its memory."),
+
+        // File Handles
+        Arg::new("handle-cache")
+            .long("handle-cache")
+            .value_parser(value_parser!(u64).range(1..))
+            .value_name("integer")
+            .value_hint(ValueHint::Other)
+            .conflicts_with("open-per-op")
+            .help("Number of files each thread keeps open. Defaults to an even share of the open file limit."),
+        Arg::new("open-per-op")
+            .long("open-per-op")
+            .action(ArgAction::SetTrue)
+            .help("Open and close the file for every operation so that the metadata cost is measured."),
+
+        Arg::new("access")
+            .long("access")
+            .default_value("sequential")
+            .value_parser(|s: &str| s.parse::<Access>())
+            .value_name("sequential|random")
+            .value_hint(ValueHint::Other)
+            .help("Order in which chapters are handed to threads. Random order differs on every pass."),
+        Arg::new("durability")
+            .long("durability")
+            .default_value("none")
+            .value_parser(|s: &str| s.parse::<Durability>())
+            .value_name("policy")
+            .value_hint(ValueHint::Other)
+            .help("When writes are forced to stable storage: none, every-write (fdatasync, measured with the write) or end-of-phase (every file is synced once the phase finishes)."),
+
+        // Write Characterization
+        Arg::new("engine")
+            .long("engine")
+            .default_value("posix")
+            .value_parser(|s: &str| s.parse::<Engine>())
+            .value_name("engine")
+            .value_hint(ValueHint::Other)
+            .help("Select the file IO interface to use. Only 'posix' is implemented."),
+    ]
+}
+
 #[allow(unused)]
 /// This function handles all aspects of creating the application context
-/// type FileConstellation. This can be either from a configuration file or from
-/// commandline arguments.
-/// TODO: 
-/// - Overwrite config parameters if cli ones are provided
-/// - This should probably return a Result<T,E>
-///     Possible Errors: 
-///         - Cannot Create FileConstellation
-///         - Config File isn't valid
-///         - Config File doesn't point valid file structures.
-fn setup_files(matches: ArgMatches) -> Result<FileConstellation> {
-
-    let files: FileConstellation;
-    if let Some(file) = matches.get_one::<PathBuf>("config") {
-        files = FileConstellation::from_configuration(file)?;
-    } 
-    else {
-
-        // Set up the file structure
-        let roots: Vec<PathBuf> = matches.get_one::<Vec<PathBuf>>("roots").unwrap().to_vec();
-        let dprefix: String     = matches.get_one::<String>("directory-prefix").unwrap().to_string();
-        let fprefix: String     = matches.get_one::<String>("file-prefix").unwrap().to_string();
-        let dcount: u64         = *matches.get_one("directory-count").unwrap();
-        let fcount: u64         = *matches.get_one("file-count").unwrap();
-        let pcount: u64         = *matches.get_one("page-count").unwrap();
-        //let direct_io: bool = *matches.get_one("o_direct").unwrap();
-
-        let root_a: PathBuf = PathBuf::from("/tmp/root.a");
-        let root_b: PathBuf = PathBuf::from("/tmp/root.b");
-        files = FileConstellation::new(
-            roots,
-            ("test_dir".to_string(), dcount),
-            ("test_file".to_string(),fcount),
-            pcount * PAGE_BYTES as u64,
-            FileOptions { directo_io: false },
-            *matches.get_one::<bool>("disable-teardown").unwrap() == false
-        )?;
+/// type FileConstellation from commandline arguments. Constellations described in a file are
+/// run with `run`; see `job::Layout`.
+///
+/// When `create` is false the constellation is assumed to exist. Nothing is created, and the
+/// constellation is never removed when it is dropped.
+fn setup_files(matches: &ArgMatches, create: bool) -> Result<FileConstellation> {
+    // Set up the file structure
+    let roots: Vec<PathBuf> = matches.get_many::<PathBuf>("roots").unwrap().cloned().collect();
+    let dprefix: String     = matches.get_one::<String>("directory-prefix").unwrap().to_string();
+    let fprefix: String     = matches.get_one::<String>("file-prefix").unwrap().to_string();
+    let dcount: u64         = *matches.get_one("directory-count").unwrap();
+    let fcount: u64         = *matches.get_one("file-count").unwrap();
+    let pcount: u64         = *matches.get_one("page-count").unwrap();
+    let psize: usize        = *matches.get_one("page-size").unwrap();
+    //let direct_io: bool = *matches.get_one("o_direct").unwrap();
+    let seed: u64           = *matches.get_one("seed").unwrap();
+    let preallocation: Preallocation = match create {
+        true  => matches.get_one::<Preallocation>("preallocate").unwrap().with_seed(seed),
+        false => Preallocation::default(),
+    };
+    let placement: FilePlacement = matches.get_one::<FilePlacement>("file-placement").unwrap().clone().with_seed(seed);
+
+    // Pages are `Page<W>`, whose size is fixed at compile time.
+    if psize != PAGE_BYTES {
+        return Err(anyhow!("invalid page size {psize}; pages are {PAGE_BYTES} bytes in this build"));
+    }
+    let sizes: FileSizes    = match (matches.get_one::<FileSizes>("file-sizes"), matches.get_one::<u64>("file-size")) {
+        (Some(sizes), _) => sizes.clone().with_seed(seed),
+        (None, Some(size)) if size % psize as u64 != 0 => {
+            return Err(anyhow!("invalid file size {size}; it must be a multiple of the page size, {psize}, or be given with '--file-sizes'"));
+        },
+        (None, Some(size)) => FileSizes::fixed(*size)?,
+        (None, None) => FileSizes::fixed(pcount * psize as u64)?,
+    };
+
+    let layers: Vec<(String, u64)> = match matches.get_one::<Vec<(String, u64)>>("directory-layers") {
+        Some(layers) => layers.clone(),
+        None         => vec![(dprefix, dcount)],
+    };
+    let options: FileOptions = FileOptions { directo_io: false, preallocation, placement };
+    match create {
+        true  => FileConstellation::nested(roots, layers, (fprefix, fcount), sizes, options, false),
+        false => FileConstellation::describe(roots, layers, (fprefix, fcount), sizes, options),
     }
-    Ok(files)
 }
 
 #[allow(unused)]
-fn setup_threads() -> (ThreadPool, usize) {
+fn setup_threads(threads: Option<usize>) -> (ThreadPool, usize) {
     // Set up thread pool
-    let available_cpus: usize = std::thread::available_parallelism().unwrap().into();
-    let pool = rayon::ThreadPoolBuilder::new().num_threads(available_cpus)
+    let threads: usize = threads.unwrap_or_else(|| std::thread::available_parallelism().unwrap().into());
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads)
+                                              .thread_name(|index| format!("worker-{index}"))
                                               .build()
                                               .unwrap();
-    (pool, available_cpus)
+    (pool, threads)
+}
+
+/// State shared by all workers while running a single mode.
+struct Phase<'a> {
+    seed: u64,
+    mode: &'a Mode,
+    files: &'a FileConstellation,
+    window: Window,
+    limits: Limits,
+    /// Bucket shared by all workers when there is a global rate limit.
+    global_limit: Option<Arc<TokenBucket>>,
+    placement: &'a Placement,
+    handles: Handles,
+    engine: Engine,
+    durability: Durability,
+    /// When a timed phase ends. Workers stop taking work once it has passed.
+    deadline: Option<Instant>,
+    /// Maps the chapters yielded by the queue onto the files.
+    chapters: &'a ChapterMap,
+    /// Shown while data is moved; metadata phases have none.
+    progress: Option<&'a Progress>,
+    /// Counts operations for the time series, when one is recorded.
+    sampler: Option<&'a Sampler>,
+    /// Operations which are tallied.
+    operations: &'a [Operation],
+    /// Records every IO, when a trace is captured.
+    tracer: Option<&'a Tracer>,
+    /// The IOs of an imported log, which the queue yields indices into in place of chapters.
+    imported: Option<&'a [(Extent, Operation)]>,
 }
 
 pub enum Mode {
     Create,
     Bench,
-    Verify,
+    Mixed(Mix),
+    /// Metadata benchmark over the given number of files per thread.
+    Metadata(Directories, u64),
+    /// The reads and writes of an IO log, made to the pages of the constellation.
+    Imported(Vec<LoggedIo>),
 } impl Mode {
-    fn _to_str(&self) -> &str {
+    fn to_str(&self) -> &str {
         match self {
             Mode::Create => "Create",
             Mode::Bench => "Bench",
-            Mode::Verify => "Verify",
+            Mode::Mixed(_) => "Mixed",
+            Mode::Metadata(..) => "Metadata",
+            Mode::Imported(_) => "Imported",
         }
 
     }
+
+    /// The operations a worker may perform in this mode.
+    fn operations(&self) -> &[Operation] {
+        match self {
+            Mode::Create   => &[Operation::Write],
+            Mode::Bench    => &[Operation::Read],
+            Mode::Mixed(_) => &[Operation::Read, Operation::Write],
+            Mode::Metadata(..) => &metadata::PHASES,
+            Mode::Imported(_) => &[Operation::Read, Operation::Write],
+        }
+    }
+}
+
+/// Exit status of every subcommand. Invalid arguments are reported by clap, with status 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Success = 0,
+    /// The subcommand failed and the error was printed.
+    Failure = 1,
+    /// The constellation on disk differs from its description.
+    Mismatch = 3,
+}
+impl From<Status> for ExitCode {
+    fn from(status: Status) -> ExitCode { ExitCode::from(status as u8) }
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
 
     let args: ArgMatches = cli_arguments().get_matches();
-    let mut modes: Vec<Mode> = Vec::new();
-    if *args.get_one("create").unwrap()    { modes.push(Mode::Create); }
-    if *args.get_one("bench").unwrap()     { modes.push(Mode::Bench); }
-    if *args.get_one("verify").unwrap()    { modes.push(Mode::Verify); }
+    let (_, matches) = args.subcommand().expect("clap requires a subcommand");
+    if let Err(e) = logging::init(*matches.get_one("log-level").expect("log-level has a default"),
+                                  matches.get_one::<PathBuf>("log-file").map(PathBuf::as_path),
+                                  matches.get_one::<PathBuf>("corruption-log").map(PathBuf::as_path)) {
+        eprintln!("Error: {e:#}");
+        return Status::Failure.into();
+    }
+    let status: Result<Status> = match args.subcommand() {
+        Some(("create", matches))  => create_constellation(matches),
+        Some(("bench", matches))   => benchmark(matches),
+        Some(("sweep", matches))   => sweep(matches),
+        Some(("verify", matches))  => verify_constellation(matches),
+        Some(("inspect", matches)) => inspect_page(matches),
+        Some(("run", matches))     => run_job(matches),
+        Some(("replay", matches))  => replay_trace(matches),
+        Some(("latency", matches)) => merge_histograms(matches),
+        Some(("destroy", matches)) => destroy_constellation(matches),
+        Some(("report", matches))  => report_constellation(matches),
+        _ => unreachable!("clap requires a known subcommand"),
+    };
+    match status {
+        Ok(status) => status.into(),
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            Status::Failure.into()
+        },
+    }
+}
+
+/// Creates the constellation, resuming one which was partly created, and writes every page.
+fn create_constellation(matches: &ArgMatches) -> Result<Status> {
+    let files: FileConstellation = setup_files(matches, true)?;
+    let seed: u64 = *matches.get_one("seed").expect("seed has a default");
+    let mut records: Records = Records::new(matches)?;
+    run_workers(&files, &Mode::Create, &worker_settings(matches), seed, Mode::Create.to_str(), matches.get_flag("verbose"), &mut records)?;
+    Ok(Status::Success)
+}
 
-    let pcount: u64 = *args.get_one("page-count").expect("a non-zero page count");
-    let seed: u64 = *args.get_one("seed").expect("seed is an integer");
+fn benchmark(matches: &ArgMatches) -> Result<Status> {
+    let seed: u64 = *matches.get_one("seed").expect("seed has a default");
+    let mode: Mode = bench_mode(matches, seed)?;
+    let files: FileConstellation = setup_files(matches, false)?;
+    require_files(&files)?;
+    let mut records: Records = Records::new(matches)?;
+    run_workers(&files, &mode, &worker_settings(matches), seed, mode.to_str(), matches.get_flag("verbose"), &mut records)?;
+    Ok(Status::Success)
+}
+
+/// Runs the benchmark once per point of the sweep, in order, and prints a row for each operation
+/// of each point once all have run. Every point is checked before the first is run.
+fn sweep(matches: &ArgMatches) -> Result<Status> {
+    let seed: u64 = *matches.get_one("seed").expect("seed has a default");
+    let mode: Mode = bench_mode(matches, seed)?;
+    let sweep: Sweep = Sweep::new(matches.get_many::<Axis>("vary").expect("vary is required").cloned().collect(),
+                                  *matches.get_one("combine").expect("combine has a default"),
+                                  matches.get_one::<IdList>("points").map(|points| points.ids().to_vec()))?;
+    let base: Workers = worker_settings(matches);
+    let points: Vec<(Vec<&str>, Workers)> = sweep.points()
+        .into_iter()
+        .map(|point| {
+            let workers: Workers = sweep.workers(&base, &point)
+                                        .map_err(|e| e.context(format!("invalid sweep point {}", point.join(", "))))?;
+            Ok((point, workers))
+        })
+        .collect::<Result<_>>()?;
+
+    let files: FileConstellation = setup_files(matches, false)?;
+    require_files(&files)?;
+    let mut records: Records = Records::new(matches)?;
+
+    let mut table = Table::new(sweep.parameters()
+                                    .map(|parameter| parameter.to_string())
+                                    .chain(["operation", "elapsed", "bytes", "ops", "ops/s", "bytes/s", "latency avg", "latency max", "p50", "p99", "p99.9"].map(String::from))
+                                    .collect());
+    for (index, (point, workers)) in points.iter().enumerate() {
+        let label: String = format!("{} {}/{}", mode.to_str(), index + 1, points.len());
+        println!("[{label}] {}", sweep.parameters().zip(point).map(|(p, v)| format!("{p}={v}")).collect::<Vec<String>>().join(" "));
+        for (operation, summary) in run_workers(&files, &mode, workers, seed, &label, matches.get_flag("verbose"), &mut records)? {
+            table.push(point.iter()
+                            .map(|value| value.to_string())
+                            .chain([
+                                operation.to_string(),
+                                format!("{:?}", summary.elapsed),
+                                summary.bytes.to_string(),
+                                summary.operations.to_string(),
+                                summary.operations_per_second().to_string(),
+                                summary.bytes_per_second().to_string(),
+                                format!("{:?}", summary.latency_mean),
+                                format!("{:?}", summary.latency_max),
+                                format!("{:?}", summary.histogram.percentile(50.0)),
+                                format!("{:?}", summary.histogram.percentile(99.0)),
+                                format!("{:?}", summary.histogram.percentile(99.9)),
+                            ])
+                            .collect());
+        }
+    }
+    print!("{table}");
+    Ok(Status::Success)
+}
 
-    let files: FileConstellation = setup_files(args).expect("directories and files created");
+/// The workload chosen by the flags of `bench_arguments`.
+fn bench_mode(matches: &ArgMatches, seed: u64) -> Result<Mode> {
+    if let Some(path) = matches.get_one::<PathBuf>("io-log") {
+        return Ok(Mode::Imported(iolog::load(path)?));
+    }
+    Ok(match matches.get_one::<Mix>("mixed") {
+        Some(mix) => Mode::Mixed(mix.with_seed(seed)),
+        None if *matches.get_one("metadata").unwrap() => {
+            Mode::Metadata(*matches.get_one("metadata-directories").expect("metadata-directories has a default"),
+                           *matches.get_one("metadata-files").expect("metadata-files has a default"))
+        },
+        None => Mode::Bench,
+    })
+}
+
+fn verify_constellation(matches: &ArgMatches) -> Result<Status> {
+    let files: FileConstellation = setup_files(matches, false)?;
+    require_files(&files)?;
+    match single_threaded_verify(&files) {
+        0 => Ok(Status::Success),
+        _ => Ok(Status::Mismatch),
+    }
+}
+
+/// Replays a trace and prints what each thread, and all threads together, measured.
+fn replay_trace(matches: &ArgMatches) -> Result<Status> {
+    let seed: u64 = *matches.get_one("seed").expect("seed has a default");
+    let records: Vec<trace::Record> = trace::load(matches.get_one::<PathBuf>("trace").expect("trace is required"))?;
+    let files: FileConstellation = setup_files(matches, false)?;
+    require_files(&files)?;
+
+    let window = Window::new(trace::threads(&records), WarmUp::None, Duration::ZERO);
+    let tallies: Vec<Tally> = trace::replay(&files, &records, *matches.get_one("timing").expect("timing has a default"), Engine::Posix, seed, &window)?;
+    tallies.iter().filter(|tally| tally.operations > 0).for_each(|tally| println!("[Replay]{}", tally));
+    [Operation::Read, Operation::Write, Operation::Sync].iter().for_each(|operation| {
+        let of_operation: Vec<Tally> = tallies.iter()
+                                              .filter(|t| t.operation == *operation)
+                                              .cloned()
+                                              .collect();
+        println!("[Replay][total][{}] {}", operation, Summary::new(&window, &of_operation));
+    });
+    Ok(Status::Success)
+}
+
+/// Prints the percentiles of each operation, or each operation of each phase, across every
+/// saved histogram, in the order first seen.
+fn merge_histograms(matches: &ArgMatches) -> Result<Status> {
+    let by_phase: bool = matches.get_flag("by-phase");
+    let mut merged: Vec<(String, Histogram)> = Vec::new();
+    for path in matches.get_many::<PathBuf>("histograms").expect("histograms are required") {
+        for recorded in Histograms::load(path)? {
+            let key: String = match by_phase {
+                true  => format!("[{}][{}]", recorded.phase, recorded.operation),
+                false => format!("[{}]", recorded.operation),
+            };
+            match merged.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, histogram)) => histogram.merge(&recorded.histogram),
+                None => merged.push((key, recorded.histogram)),
+            }
+        }
+    }
+    merged.iter().for_each(|(key, histogram)| {
+        println!("{key} {} ops, latency avg {:?} max {:?} {histogram}", histogram.count(), histogram.mean(), histogram.max());
+    });
+    Ok(Status::Success)
+}
+
+fn destroy_constellation(matches: &ArgMatches) -> Result<Status> {
+    setup_files(matches, false)?.remove()?;
+    Ok(Status::Success)
+}
+
+fn report_constellation(matches: &ArgMatches) -> Result<Status> {
+    let files: FileConstellation = setup_files(matches, false)?;
+    let survey: Survey = files.survey()?;
+    println!("{files}");
+    println!("Files:............ {survey}");
+    match survey.is_complete() {
+        true  => Ok(Status::Success),
+        false => Ok(Status::Mismatch),
+    }
+}
+
+/// Runs each phase of a job in turn and stops at the first which fails. A verify phase which
+/// finds invalid data stops the job too.
+fn run_job(matches: &ArgMatches) -> Result<Status> {
+    let job: Job = Job::load(matches.get_one::<PathBuf>("job").expect("job is required"))?;
+    let seed: u64 = job.constellation.seed;
+    let mut files: FileConstellation = job.constellation.describe()?;
+    let mut records: Records = Records::new(matches)?;
+
+    for (index, phase) in job.phases.iter().enumerate() {
+        let label: &str = phase.label();
+        println!("[{label}] phase {} of {}", index + 1, job.phases.len());
+        if !matches!(phase.kind, job::Kind::Create | job::Kind::Destroy) {
+            require_files(&files)?;
+        }
+        let mode: Mode = match phase.kind {
+            job::Kind::Create => {
+                files = files.create()?;
+                Mode::Create
+            },
+            job::Kind::DropCaches => {
+                files.drop_cache()?;
+                continue;
+            },
+            job::Kind::WarmUp | job::Kind::Read => Mode::Bench,
+            job::Kind::Mixed => Mode::Mixed(phase.mix.expect("mixed phases are validated to have a mix").with_seed(seed)),
+            job::Kind::Metadata => Mode::Metadata(phase.metadata_directories.unwrap_or_default(), phase.metadata_files.unwrap_or(1024)),
+            job::Kind::Verify => {
+                if single_threaded_verify(&files) > 0 { return Ok(Status::Mismatch); }
+                continue;
+            },
+            job::Kind::Destroy => {
+                files.remove()?;
+                break;
+            },
+        };
+        run_workers(&files, &mode, &phase.workers, seed, label, matches.get_flag("verbose"), &mut records)
+            .map_err(|e| e.context(format!("phase {} ({label}) failed", index + 1)))?;
+    }
+    Ok(Status::Success)
+}
+
+/// The worker settings given on the command line. Subcommands which don't take an option leave
+/// it at its default.
+fn worker_settings(matches: &ArgMatches) -> Workers {
+    Workers {
+        threads: matches.get_one::<u32>("threads").map(|t| *t as usize),
+        cpus: matches.get_one::<IdList>("cpus").cloned(),
+        numa_nodes: matches.get_one::<IdList>("numa-nodes").cloned(),
+        engine: *matches.get_one("engine").expect("engine has a default"),
+        access: *matches.get_one("access").expect("access has a default"),
+        durability: *matches.get_one("durability").expect("durability has a default"),
+        rate_limit: matches.get_one::<Rate>("rate-limit").copied(),
+        thread_rate_limit: matches.get_one::<Rate>("thread-rate-limit").copied(),
+        rate_burst: *matches.get_one("rate-burst").expect("rate-burst has a default"),
+        warm_up: *matches.get_one("warm-up").expect("warm-up has a default"),
+        ramp_up: *matches.get_one("ramp-up").expect("ramp-up has a default"),
+        duration: matches.try_get_one::<Duration>("duration").ok().flatten().copied(),
+        handle_cache: matches.get_one("handle-cache").copied(),
+        open_per_op: *matches.get_one("open-per-op").unwrap(),
+    }
+}
+
+/// Measurements kept beyond the printed summaries, as asked for by `sampling_arguments`.
+struct Records {
+    series: Option<Series>,
+    histograms: Option<Histograms>,
+    trace: Option<Tracer>,
+}
+impl Records {
+    fn new(matches: &ArgMatches) -> Result<Records> {
+        Ok(Records {
+            series: matches.get_one::<PathBuf>("samples")
+                           .map(|path| Series::new(path, *matches.get_one("sample-interval").expect("sample-interval has a default")))
+                           .transpose()?,
+            histograms: matches.get_one::<PathBuf>("histograms").map(|path| Histograms::new(path)),
+            trace: matches.get_one::<PathBuf>("trace").map(|path| Tracer::create(path)).transpose()?,
+        })
+    }
+}
+
+/// Fails unless every file of the constellation exists. Only `create` makes files.
+fn require_files(files: &FileConstellation) -> Result<()> {
+    let survey: Survey = files.survey()?;
+    if !survey.missing.is_empty() {
+        return Err(anyhow!("the constellation doesn't exist or is incomplete ({survey}); run 'create' with the same layout first"));
+    }
+    Ok(())
+}
+
+/// Runs `mode` over the constellation on a pool of worker threads and prints what each measured,
+/// labelled with `label`. Progress is shown while data moves, per thread too when `verbose`, and
+/// is kept in `records` when asked for. Returns the combined measurements of each operation.
+fn run_workers(files: &FileConstellation, mode: &Mode, workers: &Workers, seed: u64, label: &str, verbose: bool, records: &mut Records) -> Result<Vec<(Operation, Summary)>> {
+    workers.validate()?;
+    let (warm_up, ramp_up, limits, engine) = (workers.warm_up, workers.ramp_up, workers.limits(), workers.engine);
+    let placement: Placement = workers.placement();
+    placement.validate()?;
+    let (threads, handle_cache, open_per_op) = (workers.threads, workers.handle_cache, workers.open_per_op);
 
-    // This should check if files even needs creating
     let fcount = files.count();
 
     const P: usize = PAGES_PER_CHAPTER;
     const W: usize = PAGE_BYTES / 8 - 4;
     const B: usize = Page::<W>::PAGE_BYTES * P;
 
-    let (pool, cpus): (ThreadPool, usize) = setup_threads();
-
-
-    modes.iter()
-        .for_each(|mode| { 
-            match mode {
-               Mode::Create | Mode::Bench => {
-                   let stride: u64 = 1;
-
-                   let map = move |_l, current, _u, _i| {
-                       match current {
-                           Some(v) => Some(v + stride),
-                           None => None,
-                       }
-                   };
-
-                   let queue: DIter = DIter::new(0, fcount*pcount -1, fcount*pcount, map);
-                   let chapter = Box::new(Chapter::<P,W,B>::new());
-
-                   pool.install(|| {
-                       (0..cpus).into_par_iter()
-                                .for_each(|_|{
-                                    thread_worker::<P,W,B>(seed, 
-                                                           mode, 
-                                                           queue.clone(), 
-                                                           chapter.clone(), 
-                                                           files.clone(),
-                                     );
-                                });
-                   });
-               },
-               Mode::Verify   => single_threaded_verify(&files),
+    let (pool, cpus): (ThreadPool, usize) = setup_threads(threads);
+
+    let handles: Handles = match handle_cache {
+        _ if open_per_op => Handles::Reopen,
+        Some(capacity) => Handles::Cached(capacity as usize),
+        None => Handles::cached_within_limit(cpus)?,
+    };
+    handles.validate(cpus)?;
+
+    let chapters: ChapterMap = ChapterMap::new((0..fcount).map(|id| files.size(id)), B as u64)?;
+
+    let summaries: Vec<(Operation, Summary)> = match mode {
+        Mode::Create | Mode::Bench | Mode::Mixed(_) | Mode::Imported(_) => {
+            let imported: Option<Vec<(Extent, Operation)>> = match mode {
+                Mode::Imported(ios) => Some(iolog::map(ios, &chapters)?),
+                _ => None,
+            };
+            let (work, bytes): (u64, u64) = match &imported {
+                Some(imported) => (imported.len() as u64, imported.iter().map(|(extent, _)| extent.length).sum()),
+                None => (chapters.count(), chapters.bytes()),
+            };
+            // A timed phase passes over the constellation, or the log, until its deadline.
+            let queue: DIter = workers.access.queue(work, seed, workers.duration.is_some());
+            let deadline: Option<Instant> = workers.duration.map(|duration| Instant::now() + ramp_up + duration);
+            let progress = Progress::new(label, deadline.is_none().then_some(bytes), deadline, cpus, verbose);
+            // Opens, and syncs when writes are synced, are timed alongside the mode's operations.
+            let operations: Vec<Operation> = mode.operations()
+                                                 .iter()
+                                                 .copied()
+                                                 .chain([Operation::Open])
+                                                 .chain((workers.durability == Durability::EveryWrite).then_some(Operation::Sync))
+                                                 .collect();
+            let interval: Option<Duration> = records.series.as_ref().map(|series| series.interval());
+            let sampler: Option<Sampler> = interval.map(|_| Sampler::new(&operations));
+
+            let phase = Phase {
+                seed,
+                mode,
+                files,
+                window: Window::new(cpus, warm_up, ramp_up),
+                limits,
+                global_limit: Throttle::shared(&limits),
+                placement: &placement,
+                handles,
+                engine,
+                durability: workers.durability,
+                deadline,
+                chapters: &chapters,
+                progress: Some(&progress),
+                sampler: sampler.as_ref(),
+                operations: &operations,
+                tracer: records.trace.as_ref(),
+                imported: imported.as_deref(),
+            };
+
+            let (tallies, samples): (Vec<Tally>, Vec<_>) = std::thread::scope(|scope| {
+                let sampling = sampler.as_ref()
+                                      .zip(interval)
+                                      .map(|(sampler, interval)| scope.spawn(move || sampler.run(interval, label)));
+                let tallies: Vec<Tally> = pool.install(|| {
+                    (0..cpus).into_par_iter()
+                             .map(|thread|{
+                                 thread_worker::<P,W,B>(&phase,
+                                                        queue.clone(), 
+                                                        thread,
+                                  )
+                             })
+                             .flatten()
+                             .collect()
+                });
+                sampler.as_ref().inspect(|sampler| sampler.stop());
+                (tallies, sampling.map(|s| s.join().expect("sampler doesn't panic")).unwrap_or_default())
+            });
+            progress.finish();
+            if let Some(series) = &mut records.series { series.extend(samples)?; }
+            if let Some(tracer) = &records.trace { tracer.flush()?; }
+
+            if workers.durability == Durability::EndOfPhase { files.sync()?; }
+
+            if limits.is_limited() { println!("[{label}][offered] {}", limits); }
+            tallies.iter().for_each(|tally| println!("[{label}]{}", tally));
+            operations.iter().map(|operation| {
+                let of_operation: Vec<Tally> = tallies.iter()
+                                                      .filter(|t| t.operation == *operation)
+                                                      .cloned()
+                                                      .collect();
+                let summary: Summary = Summary::new(&phase.window, &of_operation);
+                println!("[{label}][total][{}] {}", operation, summary);
+                (*operation, summary)
+            }).collect()
+        },
+        Mode::Metadata(directories, items) => {
+            if limits.limits_bytes() {
+                return Err(anyhow!("metadata operations move no bytes, so they can only be limited in operations per second, e.g. '500iops', not to {limits}"));
             }
-        });
-    Ok(())
+            let layout: MetadataLayout = MetadataLayout::new(files, *items, *directories);
+
+            // Each operation is a phase of its own which every thread completes before the
+            // next begins.
+            metadata::PHASES.iter().map(|operation| {
+                let phase = Phase {
+                    seed,
+                    mode,
+                    files,
+                    // Warm-up is counted in bytes of data IO, which metadata operations
+                    // don't move; only ramp-up applies.
+                    window: Window::new(cpus, WarmUp::None, ramp_up),
+                    limits,
+                    global_limit: Throttle::shared(&limits),
+                    placement: &placement,
+                    handles,
+                    engine,
+                    durability: workers.durability,
+                    deadline: None,
+                    chapters: &chapters,
+                    progress: None,
+                    sampler: None,
+                    operations: mode.operations(),
+                    tracer: None,
+                    imported: None,
+                };
+
+                let tallies: Vec<Tally> = pool.install(|| {
+                    (0..cpus).into_par_iter()
+                             .map(|thread| metadata_worker(&phase, &layout, *operation, thread))
+                             .collect()
+                });
+
+                tallies.iter().for_each(|tally| println!("[{label}]{}", tally));
+                let summary: Summary = Summary::new(&phase.window, &tallies);
+                println!("[{label}][total][{}] {}", operation, summary);
+                (*operation, summary)
+            }).collect()
+        },
+    };
+    if let Some(histograms) = &mut records.histograms {
+        histograms.extend(summaries.iter().map(|(operation, summary)| Recorded {
+            phase: label.to_string(),
+            operation: operation.to_string(),
+            histogram: summary.histogram.clone(),
+        }))?;
+    }
+    Ok(summaries)
 }
 
+
 //TODO There should be some distinct function for each Read and Write mode
  fn thread_worker<const P:usize,const W: usize,const B: usize>(
-      seed: u64, 
-      mode: &Mode,
+      phase: &Phase,
       queue: DIter,
-      mut chapter: Box<Chapter<P,W,B>>,
-      files: FileConstellation,
- ) {
-     let _thread_id: usize = rayon::current_thread_index().unwrap_or(0);
-     let is_read: bool = matches!(mode, Mode::Bench);
-     let page_count_per_file: usize = files.size() as usize / PAGE_BYTES;
+      thread: usize,
+ ) -> Vec<Tally> {
+     let (seed, mode, files, window, engine) = (phase.seed, phase.mode, phase.files, &phase.window, phase.engine);
+
+     // Place the thread before allocating its buffer so the buffer is local to it.
+     let location: Location = phase.placement.apply(thread)
+                                             .expect("placement was validated before starting");
+     let mut chapter: Box<Chapter<P,W,B>> = Chapter::new_boxed();
+     let mut handles: HandleCache = HandleCache::new(files, phase.handles);
+
+     let throttle: Throttle = Throttle::new(&phase.limits, phase.global_limit.clone());
+     let mut trace = phase.tracer.map(|tracer| tracer.thread(thread));
  
      //TODO: Flesh out this verify thing more
      let verify: bool = true;
 
-     let chunk_size: u64 = PAGES_PER_CHAPTER as u64;
- 
+     let mut tallies: Vec<Tally> = phase.operations
+                                       .iter()
+                                       .map(|operation| Tally::new(thread, location, *operation))
+                                       .collect();
+
+     // Threads join the pool gradually if ramp-up was requested.
+     window.join(thread);
  
      queue.into_iter()
-          .step_by(PAGES_PER_CHAPTER)
-          .for_each(|(work, _i)| 
+          .take_while(|_| phase.deadline.is_none_or(|deadline| Instant::now() < deadline))
+          .for_each(|(work, iteration)| 
      {
-         let page_id = work % page_count_per_file as u64;
-         let file_id = work / page_count_per_file as u64;
- 
-         let mut file: File = files.open(file_id, is_read, !is_read)
-                                   .expect("files created at constellation instatiation");
+         let (extent, operation): (Extent, Operation) = match phase.imported {
+             Some(imported) => imported[work as usize],
+             None => {
+                 let extent: Extent = phase.chapters.extent(work).expect("queue yields chapters within the map");
+                 let operation: Operation = match mode {
+                     Mode::Mixed(mix) => mix.operation(iteration),
+                     Mode::Create     => Operation::Write,
+                     _                => Operation::Read,
+                 };
+                 (extent, operation)
+             },
+         };
+         let (file_id, offset, length) = (extent.file, extent.offset, extent.length as usize);
+         let is_read: bool = operation == Operation::Read;
+
+         // Writes in a mixed or imported workload mutate the pages already on disk, which
+         // requires reading them first. That read is part of preparing the write and is not
+         // measured.
+         let mutate: bool = !is_read && matches!(mode, Mode::Mixed(_) | Mode::Imported(_));
  
-         // If this isnt the start of a file, seek to the appropriate place to begin reading
-         // data.
-         if page_id != 0 {
-             file.seek(SeekFrom::Start(page_id * PAGE_BYTES as u64))
-                 .expect("file is open and seekable");
+         // Opening is only timed when the file isn't already open.
+         let opening: Option<Instant> = (!handles.is_open(file_id, is_read || mutate, !is_read)).then(Instant::now);
+         let file: &File = handles.get(file_id, is_read || mutate, !is_read)
+                                  .expect("files created at constellation instatiation");
+         if let Some(opening) = opening {
+             if window.measuring() { tally_of(&mut tallies, Operation::Open).record(0, opening); }
+             if let Some(sampler) = phase.sampler { sampler.record(Operation::Open, 0, opening.elapsed()); }
          }
+
+         if mutate {
+             let buffer: &mut [u8] = &mut chapter.mutable_bytes_all()[..length];
+             let reading: Instant = Instant::now();
+             let bytes_read: usize = engine.read_all_at(file, buffer, offset, PAGE_BYTES)
+                                           .expect("file is open for read");
+             if let Some(trace) = &mut trace { trace.record(Operation::Read, file_id, offset, length, reading, bytes_read as i64); }
+             if bytes_read < length {
+                 warn!(target: CORRUPTION, "Short read before mutation: file {file_id}, offset {offset}, {bytes_read} of {length} bytes");
+             }
+         }
+
+         throttle.acquire(length as u64);
+         let issued: Instant = Instant::now();
+         let measured: bool = window.measuring();
  
+         // Reads are retried until the extent is full, so fewer bytes means the file is shorter
+         // than the constellation says it should be.
+         let mut bytes_completed: usize = length;
          if is_read {
-             let buffer: &mut [u8] = chapter.mutable_bytes_all();
-             let bytes_read: usize = file.read(buffer).expect("file is open for read");
-
-             // This should emit a debug
-             if bytes_read == 0 || bytes_read % PAGE_BYTES != 0 { return; }
+             let buffer: &mut [u8] = &mut chapter.mutable_bytes_all()[..length];
+             bytes_completed = engine.read_all_at(file, buffer, offset, PAGE_BYTES)
+                                     .expect("file is open for read");
+             if let Some(trace) = &mut trace { trace.record(Operation::Read, file_id, offset, length, issued, bytes_completed as i64); }
+             if bytes_completed < length {
+                 warn!(target: CORRUPTION, "Short read: file {file_id}, offset {offset}, {bytes_completed} of {length} bytes");
+             }
          }  
-         
  
-         // Iterate over the range {page_id, page_id + work_chunk}
-         (page_id..(page_id+chunk_size)).for_each(|p|{ // FIXME: Incorporate chunksize into
-                                                               // queue some how?
-             let chapter_relative_page_id = p % chunk_size;
+         // Iterate over the pages of the extent. The last may be truncated by the end of the file.
+         (0..extent.pages()).for_each(|chapter_relative_page_id|{
+             let p: u64 = extent.first_page() + chapter_relative_page_id;
+             let page_bytes: usize = extent.page_bytes(chapter_relative_page_id);
              if is_read {
-                 if !chapter.mutable_page(chapter_relative_page_id).is_valid() {
-                     let (s, f, p, m) = chapter.mutable_page(chapter_relative_page_id).get_metadata();
-                     warn!("Invalid Page Found: file {file_id}, page {page_id}");
-                     warn!("Seed: 0x{s:X}\nFile: 0x{f:X}\nPage: 0x{p:X}\nMutations: 0x{m:X}");
+                 let available: usize = bytes_completed.saturating_sub(chapter_relative_page_id as usize * PAGE_BYTES)
+                                                       .min(page_bytes);
+                 if available == 0 { return; }
+                 if !chapter.page(chapter_relative_page_id).is_valid_upto(available) {
+                     let (s, f, held, m) = chapter.page(chapter_relative_page_id).get_metadata();
+                     warn!(target: CORRUPTION, "Invalid Page Found: file {file_id}, page {p}; it holds seed 0x{s:X}, file 0x{f:X}, page 0x{held:X}, mutations 0x{m:X}");
                  } 
+             } else if mutate && chapter.page(chapter_relative_page_id).is_valid_upto(page_bytes) {
+                 chapter.mutable_page(chapter_relative_page_id).mutate();
              } else {
+                 if mutate { warn!(target: CORRUPTION, "Invalid Page Found before mutation: file {file_id}, page {p}. Rewriting it."); }
                  chapter.mutable_page(chapter_relative_page_id)
                         .reinit(seed, file_id, p, 0);
                  if verify && !chapter.page(chapter_relative_page_id).is_valid() {
-                     warn!("Validation error after write. Page {p} of file {file_id} failed its validation check!"); 
+                     warn!(target: CORRUPTION, "Validation error after write. Page {p} of file {file_id} failed its validation check!"); 
                  }
              }
          });
  
          if !is_read {
-             file.write_all(chapter.bytes_all()).unwrap();
-             file.flush().expect("file is open for write");
+             engine.write_all_at(file, chapter.bytes_upto(length), offset, PAGE_BYTES)
+                   .expect("file is open for write");
+             if let Some(trace) = &mut trace { trace.record(Operation::Write, file_id, offset, length, issued, length as i64); }
+             if phase.durability == Durability::EveryWrite {
+                 let syncing: Instant = Instant::now();
+                 file.sync_data().expect("file is open for write");
+                 if let Some(trace) = &mut trace { trace.record(Operation::Sync, file_id, 0, 0, syncing, 0); }
+                 if measured { tally_of(&mut tallies, Operation::Sync).record(0, syncing); }
+                 if let Some(sampler) = phase.sampler { sampler.record(Operation::Sync, 0, syncing.elapsed()); }
+             }
          }
  
-         let _bytes_completed: u64 = chapter.byte_count() as u64;
+         if measured { tally_of(&mut tallies, operation).record(bytes_completed as u64, issued); }
+         else { window.warm(bytes_completed as u64); }
+         if let Some(progress) = phase.progress { progress.advance(thread, bytes_completed as u64); }
+         if let Some(sampler) = phase.sampler { sampler.record(operation, bytes_completed as u64, issued.elapsed()); }
      });
+     tallies
  }
 
 
+/// The tally of `operation`, one of those of the phase.
+fn tally_of(tallies: &mut [Tally], operation: Operation) -> &mut Tally {
+    tallies.iter_mut()
+           .find(|t| t.operation == operation)
+           .expect("a tally exists for each operation of the phase")
+}
+
+/// Performs `operation` on each of the thread's metadata benchmark files.
+fn metadata_worker(
+     phase: &Phase,
+     layout: &MetadataLayout,
+     operation: Operation,
+     thread: usize,
+) -> Tally {
+    let location: Location = phase.placement.apply(thread)
+                                            .expect("placement was validated before starting");
+    let throttle: Throttle = Throttle::new(&phase.limits, phase.global_limit.clone());
+    let mut tally: Tally = Tally::new(thread, location, operation);
+
+    if operation == metadata::PHASES[0] {
+        layout.prepare(thread).expect("thread's metadata directory can be created");
+    }
+
+    phase.window.join(thread);
+
+    (0..layout.items()).for_each(|item| {
+        throttle.acquire(0);
+        let issued: Instant = Instant::now();
+        let measured: bool = phase.window.measuring();
+
+        if let Err(e) = layout.perform(operation, thread, item) {
+            warn!("Failed to {operation} metadata file {item} of thread {thread}: {e}");
+            return;
+        }
+        if measured { tally.record(0, issued); }
+    });
+
+    if operation == metadata::PHASES[metadata::PHASES.len() - 1] {
+        if let Err(e) = layout.finish(thread) {
+            warn!("Failed to remove metadata directory of thread {thread}: {e}");
+        }
+    }
+    tally
+}
+
+/// Prints the page which should hold the byte at an offset of a file alongside the page on disk.
+/// The expected page takes its mutation count from the page on disk when the rest of its
+/// metadata matches, so that pages rewritten by a benchmark are not reported as corrupt.
+fn inspect_page(matches: &ArgMatches) -> Result<Status> {
+    const W: usize = PAGE_BYTES / 8 - 4;
+
+    let path: &Path = matches.get_one::<PathBuf>("path").expect("path is required");
+    let offset: u64 = *matches.get_one("offset").expect("offset is required");
+    let seed: u64 = *matches.get_one("seed").expect("seed has a default");
+    let files: FileConstellation = setup_files(matches, false)?;
+
+    let address: PageAddress = files.address(path, offset)?;
+    let file: File = files.open(address.file, true, false)?;
+    let mut on_disk: Page<W> = Page::default();
+    let available: usize = Engine::Posix.read_all_at(&file, bytemuck::bytes_of_mut(&mut on_disk), address.page_offset(), PAGE_BYTES)?;
+
+    let (s, f, p, m) = on_disk.get_metadata();
+    let mutations: u64 = if (s, f, p) == (seed, address.file, address.page) { m } else { 0 };
+    let expected: Page<W> = address.expected_page(seed, mutations);
+
+    println!("{}: {address}", path.display());
+    println!("[expected]\n{expected}");
+    println!("[on disk, {available} bytes]\n{on_disk}");
+
+    let expected_bytes: &[u8] = &bytemuck::bytes_of(&expected)[..available];
+    match bytemuck::bytes_of(&on_disk)[..available].iter().zip(expected_bytes).position(|(a, b)| a != b) {
+        None => {
+            println!("The page is valid");
+            Ok(Status::Success)
+        },
+        Some(byte) => {
+            println!("The page first differs from the expected page at byte {byte} of the page, offset {} of the file",
+                     address.page_offset() + byte as u64);
+            Ok(Status::Mismatch)
+        },
+    }
+}
+
 #[allow(unused)]
 // Keep this function around as a secondary check on 
 // multi-threaded read and verify.
 // Eventually this should be replaced with a multi-threaded
 // monotonic read-only access pattern worker
-fn single_threaded_verify(files: &FileConstellation) {
+/// Returns the number of invalid pages and files of the wrong length which were found.
+fn single_threaded_verify(files: &FileConstellation) -> u64 {
     let fcount = files.count();
+    let mut invalid: u64 = 0;
 
     const P: usize = PAGES_PER_CHAPTER;
     const W: usize = PAGE_BYTES / 8 - 4;
@@ -410,36 +1336,51 @@ fn single_threaded_verify(files: &FileConstellation) {
 
     let mut chapter = Box::new(Chapter::<P,W,B>::new());
     let now: SystemTime = SystemTime::now();
+    let progress = Progress::new("Verify", Some((0..fcount).map(|id| files.size(id)).sum()), None, 1, false);
 
     // Read from a File
     (0..fcount).into_iter()
                .for_each(|file_id| { 
-                    let mut file: File = files.open(file_id, true, false)
-                                                       .expect("files created at constellation instatiation");
+                    let file: File = files.open(file_id, true, false)
+                                          .expect("files created at constellation instatiation");
+                    let mut offset: u64 = 0;
 
                     loop {
                         let writable_buffer: &mut [u8] = chapter.mutable_bytes_all();
-                        let bytes_read: usize = file.read(writable_buffer)
-                                                    .expect("file was opened with read");
-
-                        if bytes_read == 0 || bytes_read % PAGE_BYTES != 0 { break; }
+                        let bytes_read: usize = Engine::Posix.read_all_at(&file, writable_buffer, offset, PAGE_BYTES)
+                                                             .expect("file was opened with read");
+                        if bytes_read == 0 { break; }
+                        offset += bytes_read as u64;
 
+                        // Only the final chapter of a file may be short, and its last page truncated.
                         chapter.pages_all()
                                .iter()
-                               .for_each(|page|{
-                                    if !page.is_valid() {
+                               .take(bytes_read.div_ceil(PAGE_BYTES))
+                               .enumerate()
+                               .for_each(|(i, page)|{
+                                    let available: usize = (bytes_read - i * PAGE_BYTES).min(PAGE_BYTES);
+                                    if !page.is_valid_upto(available) {
                                         let (s, f, p, m) = page.get_metadata();
-                                        println!("Invalid Page Found: file {file_id}, page {page}");
-                                        println!("Seed: 0x{s:X}\nFile: 0x{f:X}\nPage: 0x{p:X}\nMutations: 0x{m:X}");
+                                        let page: u64 = (offset - bytes_read as u64) / PAGE_BYTES as u64 + i as u64;
+                                        progress.suspend(|| warn!(target: CORRUPTION, "Invalid Page Found: file {file_id}, page {page}; it holds seed 0x{s:X}, file 0x{f:X}, page 0x{p:X}, mutations 0x{m:X}"));
+                                        invalid += 1;
                                     }
                                });
-                        work += chapter.byte_count() as u64;
+                        work += bytes_read as u64;
+                        progress.advance(0, bytes_read as u64);
+                        if bytes_read < chapter.byte_count() { break; }
+                    }
+                    if offset != files.size(file_id) {
+                        progress.suspend(|| warn!(target: CORRUPTION, "File {file_id} holds {offset} bytes but should hold {}", files.size(file_id)));
+                        invalid += 1;
                     }
                });
+    progress.finish();
     let elapsed: Duration = now.elapsed().unwrap();
     let nanos = elapsed.as_nanos();
     println!("[tid:{}][read] {}, {}, {} ns/byte", rayon::current_thread_index().unwrap_or(0),
                                                      HumanDuration(elapsed),
                                                      HumanBytes(work),
                                                      nanos / work as u128);
+    invalid
 }
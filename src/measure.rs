@@ -0,0 +1,323 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+use indicatif::{HumanBytes, HumanDuration};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use super::{affinity::Location, histogram::Histogram, workload::Operation};
+
+
+/// Condition which must be satisfied before IO is counted towards reported results.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WarmUp {
+    /// Measurement begins as soon as all threads are running.
+    None,
+    /// Measurement begins once this much time has passed since the phase started.
+    Time(Duration),
+    /// Measurement begins once all threads, combined, have moved this many bytes.
+    Bytes(u64),
+}
+impl FromStr for WarmUp {
+    type Err = anyhow::Error;
+
+    /// Parses either a byte count (`1073741824`) or a duration (`30s`, `2m 30s`). A value of
+    /// zero, or `none`, disables warm-up.
+    fn from_str(s: &str) -> Result<WarmUp> {
+        if s == "none" { return Ok(WarmUp::None); }
+
+        if let Ok(bytes) = s.parse::<u64>() {
+            return Ok(if bytes == 0 { WarmUp::None } else { WarmUp::Bytes(bytes) });
+        }
+
+        match humantime::parse_duration(s) {
+            Ok(time) if time.is_zero() => Ok(WarmUp::None),
+            Ok(time) => Ok(WarmUp::Time(time)),
+            Err(e) => Err(anyhow!("invalid warm-up '{s}'; expected a byte count or a duration such as '30s': {e}")),
+        }
+    }
+}
+
+/// # Overview
+/// A `Window` decides which IO of a phase contributes to the reported results. Every thread of a
+/// phase runs the same workload from start to finish, but IO is only _measured_ once the phase has
+/// reached a steady state. Everything before that point is warm-up and is excluded.
+///
+/// # Warm-up and Ramp-up
+/// Two things must happen before the window opens:
+/// - Ramp-up: threads join the phase one at a time, evenly spaced across the ramp-up duration.
+///   The window cannot open until the last thread has joined.
+/// - Warm-up: the condition given by `WarmUp` must be met. Time is counted from the start of
+///   the phase and bytes are counted across all threads.
+///
+/// Once open, the window stays open for the remainder of the phase. If neither warm-up nor
+/// ramp-up are requested the window is open from the start and all IO is measured.
+pub struct Window {
+    /// Number of threads expected to join the phase.
+    threads: usize,
+    warm_up: WarmUp,
+    ramp_up: Duration,
+
+    /// When the phase started. Ramp-up and time based warm-up are relative to this.
+    start: Instant,
+
+    /// Number of threads which have finished their ramp-up delay.
+    running: AtomicUsize,
+
+    /// Bytes completed while the window was closed.
+    warm_up_bytes: AtomicU64,
+
+    measuring: AtomicBool,
+    opened: OnceLock<Instant>,
+}
+impl Window {
+    pub fn new(threads: usize, warm_up: WarmUp, ramp_up: Duration) -> Window {
+        let window = Window {
+            threads,
+            warm_up,
+            ramp_up,
+            start: Instant::now(),
+            running: AtomicUsize::new(0),
+            warm_up_bytes: AtomicU64::new(0),
+            measuring: AtomicBool::new(false),
+            opened: OnceLock::new(),
+        };
+        if warm_up == WarmUp::None && ramp_up.is_zero() { window.open(); }
+        window
+    }
+
+    /// The delay, from the start of the phase, before thread `index` may begin issuing IO.
+    pub fn ramp_delay(&self, index: usize) -> Duration {
+        if self.threads == 0 { return Duration::ZERO; }
+        self.ramp_up.mul_f64(index.min(self.threads) as f64 / self.threads as f64)
+    }
+
+    /// Blocks the calling thread until its ramp-up delay has passed and then counts it as running.
+    pub fn join(&self, index: usize) {
+        let wake: Instant = self.start + self.ramp_delay(index);
+        let now: Instant = Instant::now();
+        if wake > now { std::thread::sleep(wake - now); }
+        self.running.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns whether IO issued now should be measured. This opens the window if all threads are
+    /// running and the warm-up condition has been satisfied.
+    pub fn measuring(&self) -> bool {
+        if self.measuring.load(Ordering::Acquire) { return true; }
+        if self.running.load(Ordering::Acquire) < self.threads { return false; }
+
+        let warmed: bool = match self.warm_up {
+            WarmUp::None        => true,
+            WarmUp::Time(time)  => self.start.elapsed() >= time,
+            WarmUp::Bytes(bytes) => self.warm_up_bytes.load(Ordering::Relaxed) >= bytes,
+        };
+        if warmed { self.open(); }
+        warmed
+    }
+
+    /// Accounts for IO which completed while the window was closed.
+    pub fn warm(&self, bytes: u64) {
+        self.warm_up_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns when the window opened, if it has.
+    pub fn opened(&self) -> Option<Instant> { self.opened.get().copied() }
+
+    /// Returns the number of bytes excluded from measurement.
+    pub fn excluded_bytes(&self) -> u64 { self.warm_up_bytes.load(Ordering::Relaxed) }
+
+    fn open(&self) {
+        self.opened.get_or_init(Instant::now);
+        self.measuring.store(true, Ordering::Release);
+    }
+}
+
+
+/// Measured work of a single kind of operation completed by a single thread.
+#[derive(Debug, Clone)]
+pub struct Tally {
+    pub thread: usize,
+    /// Where the thread was placed when it did the work.
+    pub location: Location,
+    pub operation: Operation,
+    pub bytes: u64,
+    pub operations: u64,
+
+    /// Sum of the time each measured operation took from issue to completion.
+    pub latency: Duration,
+    /// Longest time any single measured operation took.
+    pub latency_max: Duration,
+    /// Distribution of the time each measured operation took.
+    pub histogram: Histogram,
+
+    /// When the first measured operation was issued.
+    first: Option<Instant>,
+    /// When the last measured operation completed.
+    last: Option<Instant>,
+}
+impl Tally {
+    pub fn new(thread: usize, location: Location, operation: Operation) -> Tally {
+        Tally {
+            thread,
+            location,
+            operation,
+            bytes: 0,
+            operations: 0,
+            latency: Duration::ZERO,
+            latency_max: Duration::ZERO,
+            histogram: Histogram::new(),
+            first: None,
+            last: None
+        }
+    }
+
+    /// Records a single measured operation of `bytes` which was issued at `issued` and has just
+    /// completed.
+    pub fn record(&mut self, bytes: u64, issued: Instant) {
+        let now: Instant = Instant::now();
+        let latency: Duration = now.duration_since(issued);
+        self.first.get_or_insert(issued);
+        self.last = Some(now);
+        self.bytes += bytes;
+        self.operations += 1;
+        self.latency += latency;
+        self.latency_max = self.latency_max.max(latency);
+        self.histogram.record(latency);
+    }
+
+    /// Time between the first measured operation being issued and the last one completing.
+    pub fn elapsed(&self) -> Duration {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => last.duration_since(first),
+            _ => Duration::ZERO,
+        }
+    }
+}
+impl fmt::Display for Tally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[tid:{}]{}[{}] {}, {}, {} ops, {} ops/s, {}/s, latency avg {:?} max {:?}", self.thread,
+                                                  self.location,
+                                                  self.operation,
+                                                  HumanDuration(self.elapsed()),
+                                                  HumanBytes(self.bytes),
+                                                  self.operations,
+                                                  rate(self.operations, self.elapsed()),
+                                                  HumanBytes(rate(self.bytes, self.elapsed())),
+                                                  mean(self.latency, self.operations),
+                                                  self.latency_max)
+    }
+}
+
+
+/// Measured work of all threads of a phase, combined.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub bytes: u64,
+    pub operations: u64,
+    /// Time between the window opening and the last measured operation completing.
+    pub elapsed: Duration,
+    /// Bytes moved during warm-up and ramp-up which are not part of the above.
+    pub excluded_bytes: u64,
+    pub latency_mean: Duration,
+    pub latency_max: Duration,
+    /// The histograms of every thread, merged.
+    pub histogram: Histogram,
+}
+impl Summary {
+    pub fn new(window: &Window, tallies: &[Tally]) -> Summary {
+        let last: Option<Instant> = tallies.iter().filter_map(|t| t.last).max();
+        let elapsed: Duration = match (window.opened(), last) {
+            (Some(opened), Some(last)) => last.saturating_duration_since(opened),
+            _ => Duration::ZERO,
+        };
+        let operations: u64 = tallies.iter().map(|t| t.operations).sum();
+        Summary {
+            bytes: tallies.iter().map(|t| t.bytes).sum(),
+            operations,
+            elapsed,
+            excluded_bytes: window.excluded_bytes(),
+            latency_mean: mean(tallies.iter().map(|t| t.latency).sum(), operations),
+            latency_max: tallies.iter().map(|t| t.latency_max).max().unwrap_or_default(),
+            histogram: tallies.iter().fold(Histogram::new(), |mut merged, t| { merged.merge(&t.histogram); merged }),
+        }
+    }
+
+    pub fn operations_per_second(&self) -> u64 { rate(self.operations, self.elapsed) }
+
+    pub fn bytes_per_second(&self) -> u64 { rate(self.bytes, self.elapsed) }
+}
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}, {} ops, {} ops/s, {}/s, latency avg {:?} max {:?} {} ({} excluded as warm-up)",
+                  HumanDuration(self.elapsed),
+                  HumanBytes(self.bytes),
+                  self.operations,
+                  rate(self.operations, self.elapsed),
+                  HumanBytes(rate(self.bytes, self.elapsed)),
+                  self.latency_mean,
+                  self.latency_max,
+                  self.histogram,
+                  HumanBytes(self.excluded_bytes))
+    }
+}
+
+/// Mean duration of `count` operations which took `total` combined.
+fn mean(total: Duration, count: u64) -> Duration {
+    if count == 0 { Duration::ZERO } else { Duration::from_nanos((total.as_nanos() / count as u128) as u64) }
+}
+
+/// Bytes, or operations, per second, rounded down.
+fn rate(amount: u64, elapsed: Duration) -> u64 {
+    if elapsed.is_zero() { 0 } else { (amount as f64 / elapsed.as_secs_f64()) as u64 }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn warm_up_parsing() {
+        assert_eq!("none".parse::<WarmUp>().unwrap(), WarmUp::None);
+        assert_eq!("0".parse::<WarmUp>().unwrap(), WarmUp::None);
+        assert_eq!("4096".parse::<WarmUp>().unwrap(), WarmUp::Bytes(4096));
+        assert_eq!("1m 30s".parse::<WarmUp>().unwrap(), WarmUp::Time(Duration::from_secs(90)));
+        assert!("lukewarm".parse::<WarmUp>().is_err());
+    }
+
+    #[test]
+    fn window_opens_after_ramp_and_warm_up() {
+        // Without warm-up or ramp-up everything is measured.
+        let window = Window::new(2, WarmUp::None, Duration::ZERO);
+        assert!(window.measuring());
+
+        // Ramp-up spreads threads evenly over the duration.
+        let window = Window::new(4, WarmUp::Bytes(1024), Duration::from_secs(8));
+        assert_eq!(window.ramp_delay(0), Duration::ZERO);
+        assert_eq!(window.ramp_delay(2), Duration::from_secs(4));
+
+        // Closed until every thread has joined _and_ the warm-up bytes were moved.
+        let window = Window::new(2, WarmUp::Bytes(1024), Duration::ZERO);
+        window.join(0);
+        window.warm(2048);
+        assert!(!window.measuring());
+        window.join(1);
+        assert!(window.measuring());
+        assert!(window.opened().is_some());
+        assert_eq!(window.excluded_bytes(), 2048);
+
+        let mut tally = Tally::new(0, Location::default(), Operation::Read);
+        tally.record(512, Instant::now());
+        tally.record(512, Instant::now());
+        let summary = Summary::new(&window, &[tally]);
+        assert_eq!(summary.bytes, 1024);
+        assert_eq!(summary.operations, 2);
+        assert_eq!(summary.histogram.count(), 2);
+        assert_eq!(summary.histogram.max(), summary.latency_max);
+    }
+}
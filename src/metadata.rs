@@ -0,0 +1,187 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    path::PathBuf,
+    str::FromStr,
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use super::{constellation::FileConstellation, workload::Operation};
+
+
+/// The operations of a metadata benchmark in the order they are performed. Each is run over
+/// every item, by every thread, before the next begins so that each may be timed on its own.
+pub const PHASES: [Operation; 5] = [
+    Operation::Create,
+    Operation::Stat,
+    Operation::Open,
+    Operation::Rename,
+    Operation::Unlink,
+];
+
+/// Where the threads of a metadata benchmark place their files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Directories {
+    /// All threads spread their files over the constellation's directories, so every directory
+    /// is modified concurrently by every thread.
+    #[default]
+    Shared,
+    /// Each thread works in a private directory created within one of the constellation's
+    /// directories.
+    Unique,
+}
+impl Directories {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Directories::Shared => "shared",
+            Directories::Unique => "unique",
+        }
+    }
+}
+impl FromStr for Directories {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Directories> {
+        match s.trim() {
+            "shared" => Ok(Directories::Shared),
+            "unique" => Ok(Directories::Unique),
+            _ => Err(anyhow!("invalid directory mode '{s}'; expected 'shared' or 'unique'")),
+        }
+    }
+}
+impl fmt::Display for Directories {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// # Overview
+/// The files a metadata benchmark works on. Each thread owns `items` empty files which live
+/// alongside, but never replace, the files of a `FileConstellation`:
+///
+/// ```txt
+///    shared                          unique
+///    /root/dir0/mdtest.0.0           /root/dir0/mdtest.0/mdtest.0.0
+///    /root/dir0/mdtest.1.0           /root/dir0/mdtest.0/mdtest.0.1
+///    /root/dir1/mdtest.0.1           /root/dir1/mdtest.1/mdtest.1.0
+///    /root/dir1/mdtest.1.1           /root/dir1/mdtest.1/mdtest.1.1
+/// ```
+///
+/// Item `i` of a thread is placed in directory `i % directories` when directories are shared.
+/// When they are unique, thread `t` works in `mdtest.<t>` within directory `t % directories`.
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataLayout<'a> {
+    files: &'a FileConstellation,
+    items: u64,
+    directories: Directories,
+}
+impl<'a> MetadataLayout<'a> {
+    pub fn new(files: &'a FileConstellation, items: u64, directories: Directories) -> MetadataLayout<'a> {
+        MetadataLayout { files, items, directories }
+    }
+
+    /// Number of files each thread works on.
+    pub fn items(&self) -> u64 { self.items }
+
+    /// Path of item `item` of `thread`, or of the name it is renamed to.
+    pub fn path(&self, thread: usize, item: u64, renamed: bool) -> Result<PathBuf> {
+        let suffix: &str = if renamed { ".renamed" } else { "" };
+        Ok(self.directory(thread, item)?.join(format!("mdtest.{thread}.{item}{suffix}")))
+    }
+
+    /// Creates the private directory of `thread`, if it has one. Not part of any phase.
+    pub fn prepare(&self, thread: usize) -> Result<()> {
+        if self.directories == Directories::Unique {
+            fs::create_dir_all(self.directory(thread, 0)?)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the private directory of `thread`, if it has one. It must be empty.
+    pub fn finish(&self, thread: usize) -> Result<()> {
+        if self.directories == Directories::Unique {
+            fs::remove_dir(self.directory(thread, 0)?)?;
+        }
+        Ok(())
+    }
+
+    /// Performs `operation` on item `item` of `thread`. Items must pass through `PHASES` in order.
+    pub fn perform(&self, operation: Operation, thread: usize, item: u64) -> Result<()> {
+        match operation {
+            Operation::Create => {
+                OpenOptions::new().write(true)
+                                  .create_new(true)
+                                  .open(self.path(thread, item, false)?)?;
+            },
+            Operation::Stat   => { fs::metadata(self.path(thread, item, false)?)?; },
+            // The file is closed as soon as it is dropped.
+            Operation::Open   => { File::open(self.path(thread, item, false)?)?; },
+            Operation::Rename => fs::rename(self.path(thread, item, false)?, self.path(thread, item, true)?)?,
+            Operation::Unlink => fs::remove_file(self.path(thread, item, true)?)?,
+            Operation::Read | Operation::Write | Operation::Sync => {
+                return Err(anyhow!("{operation} is not a metadata operation"));
+            },
+        }
+        Ok(())
+    }
+
+    fn directory(&self, thread: usize, item: u64) -> Result<PathBuf> {
+        let count: u64 = self.files.directory_count();
+        match self.directories {
+            Directories::Shared => self.files.directory(item % count),
+            Directories::Unique => Ok(self.files.directory(thread as u64 % count)?.join(format!("mdtest.{thread}"))),
+        }
+    }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn phases_leave_the_constellation_untouched() {
+        use crate::{constellation::FileOptions, sizes::FileSizes};
+        use tempfile::tempdir;
+
+        let root = tempdir().expect("crate should be able to create temporary directories");
+        let files: FileConstellation = FileConstellation::new(
+            vec![root.path().to_path_buf()],
+            ("test_dir".to_string(), 2),
+            ("test_file".to_string(), 4),
+            FileSizes::fixed(1024).unwrap(),
+            FileOptions { directo_io: false, ..Default::default() },
+            true
+        ).expect("created directories and files");
+        let entries = || -> usize {
+            (0..files.directory_count()).map(|d| fs::read_dir(files.directory(d).unwrap()).unwrap().count()).sum()
+        };
+        let before: usize = entries();
+
+        for directories in [Directories::Shared, Directories::Unique] {
+            let layout = MetadataLayout::new(&files, 3, directories);
+            (0..2).for_each(|thread| layout.prepare(thread).unwrap());
+
+            for operation in PHASES {
+                (0..2).for_each(|thread| (0..3).for_each(|item| layout.perform(operation, thread, item).unwrap()));
+                if operation == Operation::Rename {
+                    assert!(layout.path(1, 2, true).unwrap().exists());
+                    assert!(!layout.path(1, 2, false).unwrap().exists());
+                }
+            }
+            (0..2).for_each(|thread| layout.finish(thread).unwrap());
+            assert_eq!(entries(), before, "{directories} directories are cleaned up");
+        }
+
+        // Items can't be created twice and data operations aren't metadata operations.
+        let layout = MetadataLayout::new(&files, 1, Directories::Shared);
+        layout.perform(Operation::Create, 0, 0).unwrap();
+        assert!(layout.perform(Operation::Create, 0, 0).is_err());
+        assert!(layout.perform(Operation::Read, 0, 0).is_err());
+        fs::remove_file(layout.path(0, 0, false).unwrap()).unwrap();
+        assert!("everywhere".parse::<Directories>().is_err());
+    }
+}
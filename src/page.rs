@@ -165,6 +165,18 @@ pub struct Page<const W: usize> {
     pub fn is_valid(&self) -> bool {
         self.validate_page_with(self.seed, self.file, self. page, self.mutations)
     }
+
+    /// As `is_valid` but only the first `bytes` bytes of the page are checked. Used for a page
+    /// truncated by the end of a file. The metadata must be present in full, i.e. `bytes` must
+    /// be at least `METADATA_BYTES`, or the page is considered invalid.
+    pub fn is_valid_upto(&self, bytes: usize) -> bool {
+        if bytes < Self::METADATA_BYTES { return false; }
+        let bytes: usize = bytes.min(Self::PAGE_BYTES);
+
+        let mut expected: Page<W> = *self;
+        expected.reinit(self.seed, self.file, self.page, self.mutations);
+        bytemuck::bytes_of(self)[..bytes] == bytemuck::bytes_of(&expected)[..bytes]
+    }
      
     /// Returns the types meta-data as a tuple.
     pub fn get_metadata(&self) -> (u64, u64, u64, u64) {
@@ -304,6 +316,21 @@ mod validation {
         assert!(Page::<4096>::PAGE_BYTES == std::mem::size_of::<Page<4096>>(), "{} != {}",Page::<4096>::PAGE_BYTES, std::mem::size_of::<Page<4096>>()) ;
     }
 
+    #[test]
+    fn truncated_page_validation() {
+        use super::Page;
+        const W: usize = 16;
+        let page: Page<W> = Page::new(0xdead, 2, 3);
+        let mut truncated: Page<W> = page;
+        truncated.data[W - 1] = !truncated.data[W - 1];
+
+        // Only the last word differs, so everything before it still validates.
+        assert!(!truncated.is_valid());
+        assert!(truncated.is_valid_upto(Page::<W>::PAGE_BYTES - 8));
+        assert!(!truncated.is_valid_upto(Page::<W>::PAGE_BYTES));
+        assert!(!page.is_valid_upto(Page::<W>::METADATA_BYTES - 1));
+    }
+
     #[test]
     fn random_page_bytes_vec() {
         const W: usize = (512 / 8) - 4; // 512 words / 8 bytes per word - 4 metadata words
@@ -0,0 +1,219 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+
+/// # Overview
+/// How the files of a `FileConstellation` are assigned to its innermost directories, and so to
+/// its roots. Directory `d` resides under root `d % roots`; a policy picks the directory of each
+/// file id. File names are unique regardless of the policy, but only `RoundRobin` and
+/// `Contiguous` place exactly the same number of files in every directory.
+///
+/// - `RoundRobin`: consecutive files go to consecutive directories, and so alternate roots.
+/// - `Contiguous`: consecutive files fill one directory, then the next directory on the same root,
+///   so each root holds a contiguous range of ids.
+/// - `Weighted`: roots receive files in proportion to a weight each, e.g. their capacity.
+/// - `Hashed`: directories are chosen by a seeded hash of the id.
+/// - `Mapped`: roots are taken from a user supplied list, one per file, which repeats.
+///
+/// Within a root, `Weighted` and `Mapped` spread files round-robin over the root's directories.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilePlacement {
+    #[default]
+    RoundRobin,
+    Contiguous,
+    /// Weight of each root, in root order.
+    Weighted(Vec<u64>),
+    /// Seed of the hash.
+    Hashed(u64),
+    Mapped(RootMap),
+}
+impl FilePlacement {
+    pub fn to_str(&self) -> &str {
+        match self {
+            FilePlacement::RoundRobin  => "round-robin",
+            FilePlacement::Contiguous  => "contiguous",
+            FilePlacement::Weighted(_) => "weighted",
+            FilePlacement::Hashed(_)   => "hashed",
+            FilePlacement::Mapped(_)   => "mapped",
+        }
+    }
+
+    /// Returns a copy which hashes using `seed`. Other policies are unchanged.
+    pub fn with_seed(self, seed: u64) -> FilePlacement {
+        match self {
+            FilePlacement::Hashed(_) => FilePlacement::Hashed(seed),
+            other => other,
+        }
+    }
+
+    /// Checks that the policy can place files across `roots` roots.
+    pub fn validate(&self, roots: u64) -> Result<()> {
+        match self {
+            FilePlacement::Weighted(weights) if weights.len() as u64 != roots || weights.iter().all(|w| *w == 0) => {
+                Err(anyhow!("{} weights given for {roots} roots; there must be one per root and not all zero", weights.len()))
+            },
+            FilePlacement::Mapped(map) => match map.roots.iter().find(|root| **root as u64 >= roots) {
+                Some(root) => Err(anyhow!("file mapped to root {root} but there are only {roots} roots")),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// The innermost directory, of `roots * per_root`, which file `id`, of `count`, is placed in.
+    pub fn directory_of(&self, id: u64, count: u64, roots: u64, per_root: u64) -> u64 {
+        let directories: u64 = roots * per_root;
+        match self {
+            FilePlacement::RoundRobin => id % directories,
+            FilePlacement::Contiguous => {
+                let block: u64 = id / count.div_ceil(directories);
+                (block % per_root) * roots + block / per_root
+            },
+            FilePlacement::Weighted(weights) => {
+                let total: u64 = weights.iter().sum();
+                let mut position: u64 = id % total;
+                let (root, weight) = weights.iter()
+                                            .enumerate()
+                                            .find(|(_, weight)| {
+                                                if position < **weight { true } else { position -= **weight; false }
+                                            })
+                                            .expect("position is less than the total weight");
+                // Files before this one, in all earlier cycles and this one, placed on the root.
+                let rank: u64 = (id / total) * weight + position;
+                (rank % per_root) * roots + root as u64
+            },
+            FilePlacement::Hashed(seed) => splitmix64(seed ^ id) % directories,
+            FilePlacement::Mapped(map) => {
+                let (root, rank) = map.rank(id);
+                (rank % per_root) * roots + root as u64
+            },
+        }
+    }
+}
+impl FromStr for FilePlacement {
+    type Err = anyhow::Error;
+
+    /// Parses `round-robin`, `contiguous`, `hashed`, `weighted:<w0>,<w1>,...` or
+    /// `mapping:<path>`. A mapping file lists the root of each file, separated by commas or
+    /// whitespace. Hashing uses seed zero; see `with_seed`.
+    fn from_str(s: &str) -> Result<FilePlacement> {
+        let invalid = || anyhow!("invalid file placement '{s}'; expected round-robin, contiguous, hashed, 'weighted:<w0>,<w1>,...' or 'mapping:<path>'");
+        match s.trim().split_once(':') {
+            None if s.trim() == "round-robin" => Ok(FilePlacement::RoundRobin),
+            None if s.trim() == "contiguous"  => Ok(FilePlacement::Contiguous),
+            None if s.trim() == "hashed"      => Ok(FilePlacement::Hashed(0)),
+            Some(("weighted", weights)) => Ok(FilePlacement::Weighted(
+                weights.split(',')
+                       .map(|w| w.trim().parse().map_err(|_| invalid()))
+                       .collect::<Result<Vec<u64>>>()?
+            )),
+            Some(("mapping", path)) => {
+                let path: PathBuf = PathBuf::from(path);
+                let contents: String = std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("unable to read file mapping {}: {e}", path.display()))?;
+                let roots: Vec<usize> = contents.split(|c: char| c == ',' || c.is_whitespace())
+                                                .filter(|r| !r.is_empty())
+                                                .map(|r| r.parse().map_err(|_| anyhow!("invalid root '{r}' in file mapping {}", path.display())))
+                                                .collect::<Result<Vec<usize>>>()?;
+                Ok(FilePlacement::Mapped(RootMap::new(roots)?))
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+impl fmt::Display for FilePlacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilePlacement::Weighted(weights) => {
+                let weights: Vec<String> = weights.iter().map(|w| w.to_string()).collect();
+                write!(f, "{}:{}", self.to_str(), weights.join(","))
+            },
+            FilePlacement::Mapped(map) => write!(f, "{} ({} entries)", self.to_str(), map.roots.len()),
+            _ => write!(f, "{}", self.to_str()),
+        }
+    }
+}
+
+/// A user supplied root for each file id. Ids beyond the end of the list wrap around to its start.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootMap {
+    roots: Vec<usize>,
+    /// Number of earlier entries in `roots` which name the same root as each entry.
+    ranks: Vec<u64>,
+    /// Number of entries which name each root.
+    counts: Vec<u64>,
+}
+impl RootMap {
+    pub fn new(roots: Vec<usize>) -> Result<RootMap> {
+        if roots.is_empty() {
+            return Err(anyhow!("a file mapping must name at least one root"));
+        }
+        let mut counts: Vec<u64> = vec![0; roots.iter().max().map_or(0, |max| max + 1)];
+        let ranks: Vec<u64> = roots.iter().map(|root| {
+            counts[*root] += 1;
+            counts[*root] - 1
+        }).collect();
+        Ok(RootMap { roots, ranks, counts })
+    }
+
+    /// The root of file `id` and the number of files with a lower id on the same root.
+    fn rank(&self, id: u64) -> (usize, u64) {
+        let length: u64 = self.roots.len() as u64;
+        let position: usize = (id % length) as usize;
+        let root: usize = self.roots[position];
+        (root, (id / length) * self.counts[root] + self.ranks[position])
+    }
+}
+
+/// A well mixed 64-bit hash; the finalizer of SplitMix64.
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z: u64 = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn policies_place_files_within_the_layout() {
+        const ROOTS: u64 = 2;
+        const PER_ROOT: u64 = 3;
+        const COUNT: u64 = 24;
+        let placed = |policy: &FilePlacement| -> Vec<u64> {
+            policy.validate(ROOTS).unwrap();
+            let mut files: Vec<u64> = vec![0; (ROOTS * PER_ROOT) as usize];
+            (0..COUNT).for_each(|id| files[policy.directory_of(id, COUNT, ROOTS, PER_ROOT) as usize] += 1);
+            files
+        };
+
+        // Both balanced policies fill every directory equally.
+        assert_eq!(placed(&FilePlacement::RoundRobin), vec![4; 6]);
+        assert_eq!(placed(&FilePlacement::Contiguous), vec![4; 6]);
+        assert_eq!(FilePlacement::RoundRobin.directory_of(7, COUNT, ROOTS, PER_ROOT), 1);
+
+        // Contiguous ids share a directory and the first half of the ids share a root.
+        let contiguous: Vec<u64> = (0..COUNT).map(|id| FilePlacement::Contiguous.directory_of(id, COUNT, ROOTS, PER_ROOT)).collect();
+        assert!(contiguous[..4].iter().all(|d| *d == contiguous[0]));
+        assert!(contiguous[..12].iter().all(|d| d % ROOTS == 0) && contiguous[12..].iter().all(|d| d % ROOTS == 1));
+
+        // Root 0 holds three quarters of the files, spread evenly over its directories.
+        let weighted: Vec<u64> = placed(&"weighted:3,1".parse().unwrap());
+        assert_eq!(weighted, vec![6, 2, 6, 2, 6, 2]);
+
+        let mapping = FilePlacement::Mapped(RootMap::new(vec![1, 1, 0]).unwrap());
+        // Root 1 takes two of every three files; each root spreads its files round-robin.
+        assert_eq!(placed(&mapping), vec![3, 6, 3, 5, 2, 5]);
+
+        let hashed: Vec<u64> = placed(&FilePlacement::Hashed(9));
+        assert_eq!(hashed.iter().sum::<u64>(), COUNT);
+
+        assert!(FilePlacement::Weighted(vec![1]).validate(ROOTS).is_err());
+        assert!(FilePlacement::Mapped(RootMap::new(vec![0, 2]).unwrap()).validate(ROOTS).is_err());
+        assert!("striped".parse::<FilePlacement>().is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    fmt::Write as _,
+    io::IsTerminal,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use indicatif::{HumanBytes, HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+
+
+/// How often progress is redrawn when nothing else moves it.
+const TICK: Duration = Duration::from_millis(250);
+
+/// How often a line of progress is printed when stdout isn't a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// # Overview
+/// Live progress of a phase which moves data. On a terminal a bar shows the bytes done out of
+/// the total, the recent and average throughput and the time left; in verbose mode a line per
+/// thread follows it. Otherwise, so as not to fill logs with redraws, a line is printed at most
+/// once every `LOG_INTERVAL`.
+///
+/// A timed phase has no total, so its time left is counted down to its deadline instead.
+pub struct Progress {
+    label: String,
+    bars: MultiProgress,
+    total: ProgressBar,
+    /// One bar per thread in verbose mode, otherwise none.
+    threads: Vec<ProgressBar>,
+    /// Whether bars are drawn rather than lines logged.
+    live: bool,
+    length: Option<u64>,
+    deadline: Option<Instant>,
+    started: Instant,
+    done: AtomicU64,
+    /// Milliseconds after `started`, and bytes done, when the last line was logged.
+    logged_at: AtomicU64,
+    logged_bytes: AtomicU64,
+}
+impl Progress {
+    pub fn new(label: &str, length: Option<u64>, deadline: Option<Instant>, threads: usize, verbose: bool) -> Progress {
+        let live: bool = std::io::stdout().is_terminal();
+        let bars = MultiProgress::with_draw_target(if live { ProgressDrawTarget::stdout() } else { ProgressDrawTarget::hidden() });
+
+        let average = |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+            let _ = write!(w, "{}/s", HumanBytes(rate(state.pos(), state.elapsed())));
+        };
+        let total: ProgressBar = bars.add(match length {
+            Some(length) => ProgressBar::new(length),
+            None => ProgressBar::no_length(),
+        });
+        let style: ProgressStyle = match length {
+            Some(_) => ProgressStyle::with_template("[{prefix}] {wide_bar} {binary_bytes}/{binary_total_bytes} ({percent}%) {binary_bytes_per_sec} now, {average} average, ETA {eta}"),
+            None => ProgressStyle::with_template("[{prefix}] {spinner} {binary_bytes} {binary_bytes_per_sec} now, {average} average, {eta} left"),
+        }.expect("templates are valid")
+         .with_key("average", average)
+         .with_key("eta", move |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+             let _ = write!(w, "{}", HumanDuration(remaining(state.pos(), state.len(), state.elapsed(), deadline)));
+         });
+        total.set_style(style);
+        total.set_prefix(label.to_string());
+
+        let threads: Vec<ProgressBar> = match verbose {
+            true => (0..threads).map(|thread| {
+                let bar: ProgressBar = bars.add(ProgressBar::no_length());
+                bar.set_style(ProgressStyle::with_template("  [tid:{prefix}] {binary_bytes} {binary_bytes_per_sec} now, {average} average")
+                                            .expect("templates are valid")
+                                            .with_key("average", average));
+                bar.set_prefix(thread.to_string());
+                bar
+            }).collect(),
+            false => Vec::new(),
+        };
+        if live {
+            total.enable_steady_tick(TICK);
+            threads.iter().for_each(|bar| bar.enable_steady_tick(TICK));
+        }
+
+        Progress {
+            label: label.to_string(),
+            bars,
+            total,
+            threads,
+            live,
+            length,
+            deadline,
+            started: Instant::now(),
+            done: AtomicU64::new(0),
+            logged_at: AtomicU64::new(0),
+            logged_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Records that `thread` has moved `bytes` more.
+    pub fn advance(&self, thread: usize, bytes: u64) {
+        self.total.inc(bytes);
+        if let Some(bar) = self.threads.get(thread) { bar.inc(bytes); }
+        let done: u64 = self.done.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if !self.live { self.log(done); }
+    }
+
+    /// Hides the bars while `f` runs so that it may print, or log, without them getting in
+    /// the way.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R { self.bars.suspend(f) }
+
+    /// Removes the bars so that a summary can be printed in their place.
+    pub fn finish(&self) {
+        self.threads.iter().for_each(|bar| bar.finish_and_clear());
+        self.total.finish_and_clear();
+    }
+
+    /// Prints a line of progress if none has been printed for `LOG_INTERVAL`. Only the thread
+    /// which claims the interval prints.
+    fn log(&self, done: u64) {
+        let elapsed: Duration = self.started.elapsed();
+        let now: u64 = elapsed.as_millis() as u64;
+        let last: u64 = self.logged_at.load(Ordering::Relaxed);
+        if now - last < LOG_INTERVAL.as_millis() as u64
+            || self.logged_at.compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+            return;
+        }
+        let recent: u64 = rate(done - self.logged_bytes.swap(done, Ordering::Relaxed).min(done), Duration::from_millis(now - last));
+        let mut line: String = format!("[{}] {}", self.label, HumanBytes(done));
+        if let Some(length) = self.length {
+            let _ = write!(line, " of {} ({}%)", HumanBytes(length), (done * 100).checked_div(length).unwrap_or(100));
+        }
+        let _ = write!(line, ", {}/s now, {}/s average, {} left",
+                             HumanBytes(recent),
+                             HumanBytes(rate(done, elapsed)),
+                             HumanDuration(remaining(done, self.length, elapsed, self.deadline)));
+        println!("{line}");
+    }
+}
+
+/// Bytes per second, rounded down.
+fn rate(bytes: u64, elapsed: Duration) -> u64 {
+    if elapsed.is_zero() { 0 } else { (bytes as f64 / elapsed.as_secs_f64()) as u64 }
+}
+
+/// Time until the deadline, if there is one, else until `length` bytes are done at the average
+/// rate so far.
+fn remaining(done: u64, length: Option<u64>, elapsed: Duration, deadline: Option<Instant>) -> Duration {
+    match (deadline, length) {
+        (Some(deadline), _) => deadline.saturating_duration_since(Instant::now()),
+        (None, Some(length)) if done > 0 => elapsed.mul_f64(length.saturating_sub(done) as f64 / done as f64),
+        _ => Duration::ZERO,
+    }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn time_left_follows_the_average_rate_or_the_deadline() {
+        let second: Duration = Duration::from_secs(1);
+        assert_eq!(remaining(25, Some(100), second, None), Duration::from_secs(3));
+        assert_eq!(remaining(0, Some(100), second, None), Duration::ZERO);
+        assert_eq!(remaining(200, Some(100), second, None), Duration::ZERO);
+        let left: Duration = remaining(25, Some(100), second, Some(Instant::now() + 60 * second));
+        assert!(left <= 60 * second && left > 59 * second);
+
+        // Progress is counted whether or not it is drawn.
+        let progress = Progress::new("test", Some(100), None, 2, true);
+        (0..2).for_each(|thread| progress.advance(thread, 25));
+        assert_eq!(progress.done.load(Ordering::Relaxed), 50);
+        assert_eq!(progress.threads[1].position(), 25);
+        progress.finish();
+    }
+}
@@ -388,6 +388,13 @@ pub mod work {
             ));
             DIter { constraints, state }
         }
+
+        /// Creates a DIter which yields nothing, for when there is no work to distribute.
+        pub fn exhausted() -> DIter {
+            let work = DIter::new(0, 0, 0, |_l, _current, _u, _iteration| None);
+            work.state.lock().expect("State mutex poisoned-- panic!").current = None;
+            work
+        }
     } impl Iterator for DIter {
         type Item = (u64,u64);
 
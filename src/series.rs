@@ -0,0 +1,220 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use serde::Serialize;
+use anyhow::{anyhow, Result};
+use super::workload::Operation;
+
+
+/// Longest a sampler sleeps before checking whether its phase has ended.
+const POLL: Duration = Duration::from_millis(20);
+
+/// What one operation of a phase did during one sampling interval.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sample {
+    pub phase: String,
+    pub operation: String,
+    /// Seconds since the phase began at which the interval ended.
+    pub time: f64,
+    /// Seconds the interval lasted. Only the last of a phase is shorter than asked for.
+    pub interval: f64,
+    pub bytes: u64,
+    pub operations: u64,
+    pub bytes_per_second: u64,
+    pub operations_per_second: u64,
+    /// Mean and longest latency of the operations completed in the interval, in microseconds.
+    pub latency_mean_us: u64,
+    pub latency_max_us: u64,
+}
+impl Sample {
+    const CSV_HEADER: &'static str = "phase,operation,time,interval,bytes,operations,bytes_per_second,operations_per_second,latency_mean_us,latency_max_us";
+
+    fn csv(&self) -> String {
+        // Phases are named by the user, so may need quoting.
+        let phase: String = match self.phase.contains([',', '"', '\n']) {
+            true  => format!("\"{}\"", self.phase.replace('"', "\"\"")),
+            false => self.phase.clone(),
+        };
+        format!("{},{},{:.3},{:.3},{},{},{},{},{},{}",
+                phase, self.operation, self.time, self.interval, self.bytes, self.operations,
+                self.bytes_per_second, self.operations_per_second, self.latency_mean_us, self.latency_max_us)
+    }
+}
+
+/// Work done by one operation since the last sample.
+#[derive(Debug, Default)]
+struct Counters {
+    bytes: AtomicU64,
+    operations: AtomicU64,
+    latency_ns: AtomicU64,
+    latency_max_ns: AtomicU64,
+}
+
+/// # Overview
+/// Counts the work of a phase's workers so that it may be sampled while they run. Workers
+/// `record` each completed operation; a separate thread `run`s alongside them and, every
+/// interval, takes what has been counted since it last looked:
+///
+/// ```txt
+///    time   0 ------ 1s ------ 2s ------ 2.4s (phase ends)
+///    sample          1         2         3 (0.4s long)
+/// ```
+///
+/// Unlike the tallies of a phase, which leave out warm-up and ramp-up, every operation is
+/// counted, since stalls during warm-up are as interesting as any other.
+#[derive(Debug)]
+pub struct Sampler {
+    started: Instant,
+    counters: Vec<(Operation, Counters)>,
+    stopped: AtomicBool,
+}
+impl Sampler {
+    pub fn new(operations: &[Operation]) -> Sampler {
+        Sampler {
+            started: Instant::now(),
+            counters: operations.iter().map(|operation| (*operation, Counters::default())).collect(),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Records a completed `operation` which moved `bytes` and took `latency`.
+    pub fn record(&self, operation: Operation, bytes: u64, latency: Duration) {
+        if let Some((_, counters)) = self.counters.iter().find(|(o, _)| *o == operation) {
+            let latency: u64 = latency.as_nanos() as u64;
+            counters.bytes.fetch_add(bytes, Ordering::Relaxed);
+            counters.operations.fetch_add(1, Ordering::Relaxed);
+            counters.latency_ns.fetch_add(latency, Ordering::Relaxed);
+            counters.latency_max_ns.fetch_max(latency, Ordering::Relaxed);
+        }
+    }
+
+    /// Samples every `interval`, labelling samples with `phase`, until `stop` is called. Then
+    /// takes a final sample of whatever remains and returns them all.
+    pub fn run(&self, interval: Duration, phase: &str) -> Vec<Sample> {
+        let mut samples: Vec<Sample> = Vec::new();
+        let mut last: Duration = Duration::ZERO;
+        loop {
+            let stopped: bool = self.stopped.load(Ordering::Relaxed);
+            let now: Duration = self.started.elapsed();
+            if stopped || now >= last + interval {
+                samples.extend(self.take(phase, now, now - last));
+                last = now;
+            }
+            if stopped { return samples; }
+            std::thread::sleep((last + interval).saturating_sub(now).clamp(Duration::from_millis(1), POLL));
+        }
+    }
+
+    /// Ends `run` once it next wakes.
+    pub fn stop(&self) { self.stopped.store(true, Ordering::Relaxed); }
+
+    /// A sample of each operation covering the `interval` which ends at `time`.
+    fn take(&self, phase: &str, time: Duration, interval: Duration) -> Vec<Sample> {
+        let per_second = |amount: u64| if interval.is_zero() { 0 } else { (amount as f64 / interval.as_secs_f64()) as u64 };
+        self.counters.iter().map(|(operation, counters)| {
+            let bytes: u64 = counters.bytes.swap(0, Ordering::Relaxed);
+            let operations: u64 = counters.operations.swap(0, Ordering::Relaxed);
+            let latency_ns: u64 = counters.latency_ns.swap(0, Ordering::Relaxed);
+            Sample {
+                phase: phase.to_string(),
+                operation: operation.to_string(),
+                time: time.as_secs_f64(),
+                interval: interval.as_secs_f64(),
+                bytes,
+                operations,
+                bytes_per_second: per_second(bytes),
+                operations_per_second: per_second(operations),
+                latency_mean_us: latency_ns.checked_div(operations).unwrap_or(0) / 1000,
+                latency_max_us: counters.latency_max_ns.swap(0, Ordering::Relaxed) / 1000,
+            }
+        }).collect()
+    }
+}
+
+/// The samples of every phase of a run, and where they are saved: a JSON array if the path
+/// ends in `.json`, otherwise CSV with a header.
+#[derive(Debug, Clone)]
+pub struct Series {
+    path: PathBuf,
+    interval: Duration,
+    samples: Vec<Sample>,
+}
+impl Series {
+    pub fn new(path: &Path, interval: Duration) -> Result<Series> {
+        if interval.is_zero() {
+            return Err(anyhow!("the sampling interval must be greater than zero"));
+        }
+        Ok(Series { path: path.to_path_buf(), interval, samples: Vec::new() })
+    }
+
+    pub fn interval(&self) -> Duration { self.interval }
+
+    pub fn samples(&self) -> &[Sample] { &self.samples }
+
+    /// Adds the samples of a phase and saves the whole series, so that the phases already run
+    /// are kept should a later one fail.
+    pub fn extend(&mut self, samples: Vec<Sample>) -> Result<()> {
+        self.samples.extend(samples);
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let mut file = fs::File::create(&self.path)
+            .map_err(|e| anyhow!("unable to write samples to {}: {e}", self.path.display()))?;
+        if self.path.extension().is_some_and(|extension| extension == "json") {
+            serde_json::to_writer_pretty(&mut file, &self.samples)?;
+            writeln!(file)?;
+        } else {
+            writeln!(file, "{}", Sample::CSV_HEADER)?;
+            self.samples.iter().try_for_each(|sample| writeln!(file, "{}", sample.csv()))?;
+        }
+        Ok(())
+    }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn samples_cover_the_phase_and_are_saved() {
+        use tempfile::tempdir;
+
+        let sampler = Sampler::new(&[Operation::Read, Operation::Write]);
+        let samples: Vec<Sample> = std::thread::scope(|s| {
+            let running = s.spawn(|| sampler.run(Duration::from_millis(40), "test"));
+            (0..4).for_each(|_| {
+                sampler.record(Operation::Read, 4096, Duration::from_micros(10));
+                sampler.record(Operation::Write, 8192, Duration::from_micros(30));
+                std::thread::sleep(Duration::from_millis(30));
+            });
+            sampler.stop();
+            running.join().unwrap()
+        });
+
+        // Every operation is sampled each interval and nothing is lost or counted twice.
+        assert!(samples.len() >= 4 && samples.len().is_multiple_of(2));
+        let reads: Vec<&Sample> = samples.iter().filter(|s| s.operation == "read").collect();
+        assert_eq!(reads.iter().map(|s| s.bytes).sum::<u64>(), 4 * 4096);
+        assert_eq!(samples.iter().map(|s| s.operations).sum::<u64>(), 8);
+        assert!(reads.windows(2).all(|pair| pair[0].time < pair[1].time));
+        assert!(samples.iter().filter(|s| s.operation == "write" && s.operations > 0).all(|s| s.latency_max_us == 30));
+
+        let directory = tempdir().expect("crate should be able to create temporary directories");
+        for name in ["samples.csv", "samples.json"] {
+            let mut series = Series::new(&directory.path().join(name), Duration::from_secs(1)).unwrap();
+            series.extend(samples.clone()).unwrap();
+            let saved: String = fs::read_to_string(directory.path().join(name)).unwrap();
+            match name.ends_with(".json") {
+                true  => assert_eq!(serde_json::from_str::<Vec<serde_json::Value>>(&saved).unwrap().len(), samples.len()),
+                false => assert_eq!(saved.lines().count(), samples.len() + 1),
+            }
+        }
+        assert!(Series::new(Path::new("samples.csv"), Duration::ZERO).is_err());
+    }
+}
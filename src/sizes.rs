@@ -0,0 +1,248 @@
+use std::{fmt, str::FromStr};
+use rand_xoshiro::{
+    Xoroshiro128PlusPlus,
+    rand_core::{RngCore, SeedableRng},
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use indicatif::HumanBytes;
+
+
+/// File sizes are multiples of this many bytes.
+pub const SIZE_ALIGNMENT: u64 = 64;
+
+/// Largest file a lognormal distribution draws when no maximum is given, 1 TiB.
+pub const LOGNORMAL_MAX: u64 = 1 << 40;
+
+/// The shape of a `FileSizes` distribution. All sizes are in bytes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Distribution {
+    /// Every file has the same size.
+    Fixed(u64),
+    /// Sizes are drawn uniformly from the inclusive range `[min, max]`.
+    Uniform { min: u64, max: u64 },
+    /// Sizes are drawn from a lognormal distribution with the given median and the standard
+    /// deviation, `sigma`, of the underlying normal distribution. Draws above `max` are `max`.
+    LogNormal {
+        median: u64,
+        sigma: f64,
+        #[serde(default = "lognormal_max")]
+        max: u64,
+    },
+    /// Sizes are drawn from an explicit histogram of `(size, weight)` buckets.
+    Histogram(Vec<(u64, u64)>),
+}
+
+/// # Overview
+/// The sizes of the files of a constellation. A file's size is derived from a seed and its id
+/// alone, so it can be recomputed at any time rather than stored. Sizes are rounded down to a
+/// multiple of `SIZE_ALIGNMENT` and are never smaller than it.
+///
+/// # Examples
+/// ```
+/// # use super_massive_io::sizes::FileSizes;
+/// let sizes: FileSizes = "uniform:4KiB-1MiB".parse::<FileSizes>().unwrap().with_seed(7);
+///
+/// assert!((0..1_000).all(|id| (4096..=1 << 20).contains(&sizes.size_of(id))));
+/// assert_eq!(sizes.size_of(42), sizes.size_of(42));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSizes {
+    distribution: Distribution,
+    seed: u64,
+}
+impl FileSizes {
+    pub fn new(distribution: Distribution, seed: u64) -> Result<FileSizes> {
+        let sizes: FileSizes = FileSizes { distribution, seed };
+        sizes.validate()?;
+        Ok(sizes)
+    }
+
+    /// Every file is `size` bytes.
+    pub fn fixed(size: u64) -> Result<FileSizes> { FileSizes::new(Distribution::Fixed(size), 0) }
+
+    /// Returns a copy of these sizes which derives each file's size from `seed`.
+    pub fn with_seed(self, seed: u64) -> FileSizes { FileSizes { seed, ..self } }
+
+    pub fn distribution(&self) -> &Distribution { &self.distribution }
+
+    /// The largest size, in bytes, any file may have.
+    pub fn largest(&self) -> u64 {
+        let largest: u64 = match &self.distribution {
+            Distribution::Fixed(size) => *size,
+            Distribution::Uniform { max, .. } | Distribution::LogNormal { max, .. } => *max,
+            Distribution::Histogram(buckets) => buckets.iter().map(|(size, _)| *size).max().unwrap_or(0),
+        };
+        (largest / SIZE_ALIGNMENT * SIZE_ALIGNMENT).max(SIZE_ALIGNMENT)
+    }
+
+    /// Size, in bytes, of file `id`.
+    pub fn size_of(&self, id: u64) -> u64 {
+        let mut rng = Xoroshiro128PlusPlus::seed_from_u64(self.seed ^ id);
+        let size: u64 = match &self.distribution {
+            Distribution::Fixed(size) => *size,
+            Distribution::Uniform { min, max } => min + rng.next_u64() % (max - min + 1),
+            Distribution::LogNormal { median, sigma, max } => {
+                // Box-Muller transform of two uniform samples in (0, 1] and [0, 1).
+                let unit = |rng: &mut Xoroshiro128PlusPlus| (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+                let (u1, u2): (f64, f64) = (1.0 - unit(&mut rng), unit(&mut rng));
+                let normal: f64 = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+                ((*median as f64 * (sigma * normal).exp()) as u64).min(*max)
+            },
+            Distribution::Histogram(buckets) => {
+                let total: u64 = buckets.iter().map(|(_, weight)| weight).sum();
+                let mut roll: u64 = rng.next_u64() % total;
+                buckets.iter()
+                       .find(|(_, weight)| {
+                           if roll < *weight { true } else { roll -= weight; false }
+                       })
+                       .map(|(size, _)| *size)
+                       .expect("roll is less than the total weight")
+            },
+        };
+        (size / SIZE_ALIGNMENT * SIZE_ALIGNMENT).max(SIZE_ALIGNMENT)
+    }
+
+    fn validate(&self) -> Result<()> {
+        match &self.distribution {
+            Distribution::Fixed(size) => {
+                if *size == 0 {
+                    return Err(anyhow!("invalid file size; file size must be non-zero"));
+                }
+                // NOTE: Is this really a constraint?
+                if *size % SIZE_ALIGNMENT != 0 {
+                    return Err(anyhow!("invalid file byte alignment; file size must be a multiple of {SIZE_ALIGNMENT}"));
+                }
+            },
+            Distribution::Uniform { min, max } => {
+                if *min == 0 || min > max {
+                    return Err(anyhow!("invalid file size range {min}-{max}; minimum must be non-zero and at most the maximum"));
+                }
+            },
+            Distribution::LogNormal { median, sigma, max } => {
+                if *median == 0 || !sigma.is_finite() || *sigma < 0.0 || max < median {
+                    return Err(anyhow!("invalid lognormal file sizes; median must be non-zero, sigma a non-negative number and the maximum at least the median"));
+                }
+            },
+            Distribution::Histogram(buckets) => {
+                if buckets.is_empty() || buckets.iter().any(|(size, _)| *size == 0)
+                                      || buckets.iter().map(|(_, weight)| weight).sum::<u64>() == 0 {
+                    return Err(anyhow!("invalid file size histogram; sizes must be non-zero and weights must not all be zero"));
+                }
+            },
+        }
+        Ok(())
+    }
+}
+impl FromStr for FileSizes {
+    type Err = anyhow::Error;
+
+    /// Parses a distribution of one of the forms below. Sizes take the suffixes accepted by
+    /// `parse_bytes`. The seed is zero; see `with_seed`.
+    ///
+    /// - `4MiB`: fixed
+    /// - `uniform:4KiB-64MiB`: uniform over an inclusive range
+    /// - `lognormal:1MiB,1.5[,64GiB]`: lognormal with a median, sigma and optional maximum,
+    ///   `LOGNORMAL_MAX` by default
+    /// - `histogram:4KiB=50,1MiB=30,64MiB=20`: sizes with relative weights
+    fn from_str(s: &str) -> Result<FileSizes> {
+        let invalid = || anyhow!("invalid file sizes '{s}'; expected a size, 'uniform:<min>-<max>', 'lognormal:<median>,<sigma>[,<max>]' or 'histogram:<size>=<weight>,...'");
+        let distribution: Distribution = match s.trim().split_once(':') {
+            None => Distribution::Fixed(parse_bytes(s)?),
+            Some(("uniform", range)) => {
+                let (min, max) = range.split_once('-').ok_or_else(invalid)?;
+                Distribution::Uniform { min: parse_bytes(min)?, max: parse_bytes(max)? }
+            },
+            Some(("lognormal", parameters)) => {
+                let (median, sigma) = parameters.split_once(',').ok_or_else(invalid)?;
+                let (sigma, max) = match sigma.split_once(',') {
+                    Some((sigma, max)) => (sigma, parse_bytes(max)?),
+                    None => (sigma, LOGNORMAL_MAX),
+                };
+                Distribution::LogNormal { median: parse_bytes(median)?, sigma: sigma.trim().parse().map_err(|_| invalid())?, max }
+            },
+            Some(("histogram", buckets)) => Distribution::Histogram(
+                buckets.split(',')
+                       .map(|bucket| {
+                           let (size, weight) = bucket.split_once('=').ok_or_else(invalid)?;
+                           Ok((parse_bytes(size)?, weight.trim().parse().map_err(|_| invalid())?))
+                       })
+                       .collect::<Result<Vec<(u64, u64)>>>()?
+            ),
+            Some(_) => return Err(invalid()),
+        };
+        FileSizes::new(distribution, 0)
+    }
+}
+impl fmt::Display for FileSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.distribution {
+            Distribution::Fixed(size) => write!(f, "{}", HumanBytes(*size)),
+            Distribution::Uniform { min, max } => write!(f, "uniform {}-{}", HumanBytes(*min), HumanBytes(*max)),
+            Distribution::LogNormal { median, sigma, max } => write!(f, "lognormal median {}, sigma {sigma}, max {}", HumanBytes(*median), HumanBytes(*max)),
+            Distribution::Histogram(buckets) => {
+                let buckets: Vec<String> = buckets.iter().map(|(size, weight)| format!("{}={weight}", HumanBytes(*size))).collect();
+                write!(f, "histogram {}", buckets.join(","))
+            },
+        }
+    }
+}
+
+fn lognormal_max() -> u64 { LOGNORMAL_MAX }
+
+/// Parses a byte count of the form `<n>[unit]` where the unit is one of `B`, `K`, `KiB`, `M`,
+/// `MiB`, `G` or `GiB` (all powers of 1024).
+pub fn parse_bytes(s: &str) -> Result<u64> {
+    let s: &str = s.trim();
+    let split: usize = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse()
+                            .map_err(|_| anyhow!("invalid size '{s}'; expected a leading integer"))?;
+    let shift: u32 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b"           => 0,
+        "k" | "kib" | "kb" => 10,
+        "m" | "mib" | "mb" => 20,
+        "g" | "gib" | "gb" => 30,
+        _ => return Err(anyhow!("invalid size '{s}'; unknown unit '{unit}'")),
+    };
+    number.checked_mul(1 << shift).ok_or_else(|| anyhow!("invalid size '{s}'; too large"))
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn distributions() {
+        assert_eq!("4MiB".parse::<FileSizes>().unwrap().size_of(3), 4 << 20);
+        assert!("100".parse::<FileSizes>().is_err());
+        assert!("uniform:8K-4K".parse::<FileSizes>().is_err());
+        assert!("lognormal:1MiB,-1".parse::<FileSizes>().is_err());
+        assert!("histogram:4K=0".parse::<FileSizes>().is_err());
+        assert!("gaussian:1M".parse::<FileSizes>().is_err());
+
+        // Sizes depend on the seed and id only, and are aligned.
+        let lognormal: FileSizes = "lognormal:1MiB,1.0".parse::<FileSizes>().unwrap().with_seed(11);
+        let sizes: Vec<u64> = (0..4_001).map(|id| lognormal.size_of(id)).collect();
+        assert!(sizes.iter().all(|size| size % SIZE_ALIGNMENT == 0 && *size >= SIZE_ALIGNMENT));
+        assert_eq!(sizes, (0..4_001).map(|id| lognormal.size_of(id)).collect::<Vec<u64>>());
+        let mut sorted: Vec<u64> = sizes.clone();
+        sorted.sort();
+        assert!(((1 << 20) * 9 / 10..(1 << 20) * 11 / 10).contains(&sorted[2_000]), "median is near 1MiB");
+
+        // However wide the distribution, draws stay within the maximum.
+        let wide: FileSizes = "lognormal:1MiB,50".parse::<FileSizes>().unwrap().with_seed(11);
+        assert_eq!(wide.largest(), LOGNORMAL_MAX);
+        assert!((0..4_001).map(|id| wide.size_of(id)).any(|size| size == LOGNORMAL_MAX));
+        let capped: FileSizes = "lognormal:1MiB,50,64MiB".parse::<FileSizes>().unwrap().with_seed(11);
+        assert!((0..4_001).all(|id| capped.size_of(id) <= 64 << 20));
+        assert!("lognormal:1MiB,1.0,4KiB".parse::<FileSizes>().is_err());
+
+        // Buckets are chosen in proportion to their weight.
+        let histogram: FileSizes = "histogram:4K=75,1M=25".parse::<FileSizes>().unwrap().with_seed(3);
+        let small: usize = (0..10_000).filter(|id| histogram.size_of(*id) == 4096).count();
+        assert!((7_200..7_800).contains(&small));
+        assert!((0..10_000).all(|id| [4096, 1 << 20].contains(&histogram.size_of(id))));
+    }
+}
@@ -0,0 +1,324 @@
+use std::{fmt, str::FromStr};
+use anyhow::{anyhow, Context, Result};
+use super::job::Workers;
+
+
+/// Most values one parameter of a sweep may take.
+pub const MAX_VALUES: usize = 1024;
+
+/// Most points a sweep may have, before any are chosen with `only`.
+pub const MAX_POINTS: usize = 4096;
+
+/// A worker setting which a sweep may vary. Each matches the command line flag of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parameter {
+    Threads,
+    Engine,
+    Access,
+    Durability,
+    RateLimit,
+    ThreadRateLimit,
+    HandleCache,
+}
+impl Parameter {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Parameter::Threads         => "threads",
+            Parameter::Engine          => "engine",
+            Parameter::Access          => "access",
+            Parameter::Durability      => "durability",
+            Parameter::RateLimit       => "rate-limit",
+            Parameter::ThreadRateLimit => "thread-rate-limit",
+            Parameter::HandleCache     => "handle-cache",
+        }
+    }
+
+    /// Sets this parameter of `workers` to `value`.
+    pub fn apply(&self, workers: &mut Workers, value: &str) -> Result<()> {
+        let integer = || -> Result<u64> {
+            value.trim().parse().map_err(|_| anyhow!("invalid {self} '{value}'; expected an integer"))
+        };
+        match self {
+            Parameter::Threads         => workers.threads = Some(integer()? as usize),
+            Parameter::Engine          => workers.engine = value.parse()?,
+            Parameter::Access          => workers.access = value.parse()?,
+            Parameter::Durability      => workers.durability = value.parse()?,
+            Parameter::RateLimit       => workers.rate_limit = Some(value.parse()?),
+            Parameter::ThreadRateLimit => workers.thread_rate_limit = Some(value.parse()?),
+            Parameter::HandleCache     => workers.handle_cache = Some(integer()?),
+        }
+        workers.validate()
+    }
+}
+impl FromStr for Parameter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Parameter> {
+        match s.trim() {
+            "threads"           => Ok(Parameter::Threads),
+            "engine"            => Ok(Parameter::Engine),
+            "access"            => Ok(Parameter::Access),
+            "durability"        => Ok(Parameter::Durability),
+            "rate-limit"        => Ok(Parameter::RateLimit),
+            "thread-rate-limit" => Ok(Parameter::ThreadRateLimit),
+            "handle-cache"      => Ok(Parameter::HandleCache),
+            _ => Err(anyhow!("unknown sweep parameter '{s}'; expected threads, engine, access, durability, rate-limit, thread-rate-limit or handle-cache")),
+        }
+    }
+}
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// The values a sweep gives one parameter, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Axis {
+    pub parameter: Parameter,
+    pub values: Vec<String>,
+}
+impl FromStr for Axis {
+    type Err = anyhow::Error;
+
+    /// Parses `<parameter>=<values>`. Values are separated by commas and each may be an
+    /// inclusive integer range, `<first>..<last>`, which counts up by one, or
+    /// `<first>..<last>*<factor>`, which multiplies. `threads=1..8*2` gives 1, 2, 4 and 8. There
+    /// may be at most `MAX_VALUES` values.
+    fn from_str(s: &str) -> Result<Axis> {
+        let (parameter, values) = s.split_once('=')
+                                   .ok_or_else(|| anyhow!("invalid sweep '{s}'; expected '<parameter>=<values>'"))?;
+        let parameter: Parameter = parameter.parse()?;
+        let mut expanded: Vec<String> = Vec::new();
+        for value in values.split(',').map(str::trim) {
+            match value.split_once("..") {
+                // Ranges are expanded no further than the limit, however large they are.
+                Some((first, last)) => expanded.extend(range(first, last).with_context(|| format!("invalid {parameter} range '{value}'"))?
+                                                                         .take(MAX_VALUES + 1 - expanded.len().min(MAX_VALUES))
+                                                                         .map(|v| v.to_string())),
+                None if value.is_empty() => return Err(anyhow!("invalid sweep '{s}'; values can't be empty")),
+                None => expanded.push(value.to_string()),
+            }
+            if expanded.len() > MAX_VALUES {
+                return Err(anyhow!("invalid sweep '{s}'; {parameter} may take at most {MAX_VALUES} values"));
+            }
+        }
+        Ok(Axis { parameter, values: expanded })
+    }
+}
+
+/// Integers from `first` to `last`, inclusive. `last` may end in `*<factor>` to multiply rather
+/// than count.
+fn range(first: &str, last: &str) -> Result<impl Iterator<Item = u64>> {
+    let (last, factor) = match last.split_once('*') {
+        Some((last, factor)) => (last, Some(factor.trim().parse::<u64>()?)),
+        None => (last, None),
+    };
+    let (first, last): (u64, u64) = (first.trim().parse()?, last.trim().parse()?);
+    if first > last {
+        return Err(anyhow!("{first} is greater than {last}"));
+    }
+    if factor.is_some_and(|factor| factor < 2) || (factor.is_some() && first == 0) {
+        return Err(anyhow!("a multiplying range must start above zero and have a factor of at least 2"));
+    }
+    Ok(std::iter::successors(Some(first), move |v| match factor {
+        Some(factor) => v.checked_mul(factor),
+        None => v.checked_add(1),
+    }).take_while(move |v| *v <= last))
+}
+
+/// How the values of several axes are combined into the points of a sweep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Combination {
+    /// Every combination of values.
+    #[default]
+    Product,
+    /// The n-th value of every axis together; all axes must have as many values.
+    Zip,
+}
+impl Combination {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Combination::Product => "product",
+            Combination::Zip     => "zip",
+        }
+    }
+}
+impl FromStr for Combination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Combination> {
+        match s.trim() {
+            "product" => Ok(Combination::Product),
+            "zip"     => Ok(Combination::Zip),
+            _ => Err(anyhow!("invalid combination '{s}'; expected 'product' or 'zip'")),
+        }
+    }
+}
+impl fmt::Display for Combination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// # Overview
+/// A set of runs of one workload which differ only in some worker settings. Each point of the
+/// sweep sets one value of every axis over a common set of base settings.
+///
+/// ```txt
+///    threads=1,2   access=sequential,random
+///
+///    product: (1, sequential) (1, random) (2, sequential) (2, random)
+///    zip:     (1, sequential) (2, random)
+/// ```
+///
+/// Points can be restricted further to a chosen subset by their index, in the above order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sweep {
+    axes: Vec<Axis>,
+    combination: Combination,
+    /// Indices of the points to run. All points are run when not given.
+    only: Option<Vec<usize>>,
+}
+impl Sweep {
+    pub fn new(axes: Vec<Axis>, combination: Combination, only: Option<Vec<usize>>) -> Result<Sweep> {
+        if axes.is_empty() {
+            return Err(anyhow!("a sweep must vary at least one parameter"));
+        }
+        if let Some(axis) = axes.iter().enumerate().find_map(|(i, a)| axes[..i].iter().find(|b| b.parameter == a.parameter)) {
+            return Err(anyhow!("{} is swept more than once", axis.parameter));
+        }
+        if combination == Combination::Zip && axes.iter().any(|axis| axis.values.len() != axes[0].values.len()) {
+            return Err(anyhow!("zipped parameters must all have the same number of values"));
+        }
+        if let Some(axis) = axes.iter().find(|axis| axis.values.len() > MAX_VALUES) {
+            return Err(anyhow!("{} has {} values but may take at most {MAX_VALUES}", axis.parameter, axis.values.len()));
+        }
+        let points: Option<usize> = match combination {
+            Combination::Product => axes.iter().try_fold(1usize, |points, axis| points.checked_mul(axis.values.len())),
+            Combination::Zip => Some(axes[0].values.len()),
+        };
+        if points.is_none_or(|points| points > MAX_POINTS) {
+            return Err(anyhow!("the sweep has more than {MAX_POINTS} points; vary fewer parameters or values"));
+        }
+        let sweep = Sweep { axes, combination, only };
+        let count: usize = sweep.all_points().len();
+        if let Some(index) = sweep.only.iter().flatten().find(|index| **index >= count) {
+            return Err(anyhow!("point {index} was chosen but the sweep has only {count} points"));
+        }
+        Ok(sweep)
+    }
+
+    pub fn parameters(&self) -> impl Iterator<Item = Parameter> + '_ {
+        self.axes.iter().map(|axis| axis.parameter)
+    }
+
+    /// The value of each axis at every point which is run, in order.
+    pub fn points(&self) -> Vec<Vec<&str>> {
+        let points: Vec<Vec<&str>> = self.all_points();
+        match &self.only {
+            Some(only) => only.iter().map(|index| points[*index].clone()).collect(),
+            None => points,
+        }
+    }
+
+    /// The settings of a point: `base` with each axis set to its value at the point.
+    pub fn workers(&self, base: &Workers, point: &[&str]) -> Result<Workers> {
+        let mut workers: Workers = base.clone();
+        for (axis, value) in self.axes.iter().zip(point) {
+            axis.parameter.apply(&mut workers, value)?;
+        }
+        Ok(workers)
+    }
+
+    fn all_points(&self) -> Vec<Vec<&str>> {
+        match self.combination {
+            Combination::Product => self.axes.iter().fold(vec![Vec::new()], |points, axis| {
+                points.iter()
+                      .flat_map(|point| axis.values.iter().map(move |value| {
+                          let mut point: Vec<&str> = point.clone();
+                          point.push(value.as_str());
+                          point
+                      }))
+                      .collect()
+            }),
+            Combination::Zip => (0..self.axes[0].values.len())
+                .map(|i| self.axes.iter().map(|axis| axis.values[i].as_str()).collect())
+                .collect(),
+        }
+    }
+}
+
+/// Rows of text printed with each column padded to its widest cell.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+impl Table {
+    pub fn new(header: Vec<String>) -> Table {
+        Table { header, rows: Vec::new() }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        debug_assert_eq!(row.len(), self.header.len());
+        self.rows.push(row);
+    }
+}
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let widths: Vec<usize> = (0..self.header.len())
+            .map(|column| std::iter::once(&self.header).chain(&self.rows).map(|row| row[column].len()).max().unwrap_or(0))
+            .collect();
+        for row in std::iter::once(&self.header).chain(&self.rows) {
+            let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{cell:<width$}")).collect();
+            writeln!(f, "{}", cells.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn sweeps_expand_ranges_and_combine_axes() {
+        let threads: Axis = "threads=1..8*2,12".parse().unwrap();
+        assert_eq!(threads.values, vec!["1", "2", "4", "8", "12"]);
+        assert_eq!("handle-cache=3..5".parse::<Axis>().unwrap().values, vec!["3", "4", "5"]);
+        assert!("threads=4..1".parse::<Axis>().is_err());
+        assert!("threads=0..8*2".parse::<Axis>().is_err());
+        assert!("depth=1".parse::<Axis>().is_err());
+        assert!("page-size=4KiB".parse::<Axis>().is_err());
+
+        // Neither an axis nor the whole sweep may grow without bound.
+        assert!("threads=1..4000000000".parse::<Axis>().is_err());
+        assert_eq!(format!("threads=1..{MAX_VALUES}").parse::<Axis>().unwrap().values.len(), MAX_VALUES);
+        assert!(format!("threads=1..{MAX_VALUES},0").parse::<Axis>().is_err());
+        let wide: Axis = "threads=1..100".parse().unwrap();
+        let wider: Axis = "handle-cache=1..100".parse().unwrap();
+        assert!(Sweep::new(vec![wide, wider], Combination::Product, None).is_err());
+
+        let access: Axis = "access=sequential,random".parse().unwrap();
+        let product = Sweep::new(vec![threads.clone(), access.clone()], Combination::Product, None).unwrap();
+        assert_eq!(product.points().len(), 10);
+        assert_eq!(product.points()[1], vec!["1", "random"]);
+
+        let subset = Sweep::new(vec![threads.clone(), access.clone()], Combination::Product, Some(vec![9, 0])).unwrap();
+        assert_eq!(subset.points(), vec![vec!["12", "random"], vec!["1", "sequential"]]);
+        assert!(Sweep::new(vec![access.clone()], Combination::Product, Some(vec![2])).is_err());
+
+        let two: Axis = "threads=1,2".parse().unwrap();
+        let zip = Sweep::new(vec![two.clone(), access.clone()], Combination::Zip, None).unwrap();
+        assert_eq!(zip.points(), vec![vec!["1", "sequential"], vec!["2", "random"]]);
+        assert!(Sweep::new(vec![threads, access.clone()], Combination::Zip, None).is_err());
+        assert!(Sweep::new(vec![two.clone(), two], Combination::Product, None).is_err());
+
+        // Points are applied over the base settings and unsupported values are refused.
+        let workers: Workers = zip.workers(&Workers::default(), &zip.points()[1]).unwrap();
+        assert_eq!((workers.threads, workers.access), (Some(2), crate::workload::Access::Random));
+        assert!(zip.workers(&Workers::default(), &["0", "random"]).is_err());
+    }
+}
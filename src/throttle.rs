@@ -0,0 +1,216 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+
+/// A target rate of IO, per second.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Rate {
+    /// Bytes per second.
+    Bytes(u64),
+    /// Operations per second, regardless of their size.
+    Operations(u64),
+}
+impl Rate {
+    /// The number of tokens an operation of `bytes` consumes under this rate.
+    #[inline(always)]
+    fn cost(&self, bytes: u64) -> f64 {
+        match self {
+            Rate::Bytes(_)      => bytes as f64,
+            Rate::Operations(_) => 1.0,
+        }
+    }
+
+    #[inline(always)]
+    fn per_second(&self) -> f64 {
+        match self {
+            Rate::Bytes(r) | Rate::Operations(r) => *r as f64,
+        }
+    }
+}
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    /// Parses rates of the form `<n>[unit][/s]`. Units of `iops` or `ops` give an operation
+    /// rate. Otherwise the rate is in bytes and may carry a suffix of `B`, `K`, `KiB`, `M`,
+    /// `MiB`, `G` or `GiB` (all powers of 1024).
+    fn from_str(s: &str) -> Result<Rate> {
+        let s: &str = s.trim().trim_end_matches("/s");
+        let split: usize = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: u64 = number.parse()
+                                .map_err(|_| anyhow!("invalid rate '{s}'; expected a leading integer"))?;
+
+        let bytes = |shift: u32| -> Result<Rate> {
+            number.checked_mul(1 << shift)
+                  .map(Rate::Bytes)
+                  .ok_or_else(|| anyhow!("invalid rate '{s}'; out of range"))
+        };
+        let rate: Rate = match unit.trim().to_ascii_lowercase().as_str() {
+            "iops" | "ops"     => Rate::Operations(number),
+            "" | "b"           => Rate::Bytes(number),
+            "k" | "kib" | "kb" => bytes(10)?,
+            "m" | "mib" | "mb" => bytes(20)?,
+            "g" | "gib" | "gb" => bytes(30)?,
+            _ => return Err(anyhow!("invalid rate '{s}'; unknown unit '{unit}'")),
+        };
+
+        if rate.per_second() == 0.0 {
+            return Err(anyhow!("invalid rate '{s}'; rate must be non-zero"));
+        }
+        Ok(rate)
+    }
+}
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rate::Bytes(r)      => write!(f, "{}/s", HumanBytes(*r)),
+            Rate::Operations(r) => write!(f, "{} ops/s", r),
+        }
+    }
+}
+
+/// # Overview
+/// A token bucket which paces callers to a `Rate`. The bucket holds at most `burst` worth of
+/// tokens and refills continuously. A caller takes tokens for the IO it is about to issue and, if
+/// the bucket runs dry, sleeps until the tokens it took would have been refilled.
+///
+/// Tokens are reserved under a lock but callers sleep outside of it, so a single bucket may be
+/// shared between threads to pace them collectively.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: Rate,
+    /// Maximum number of tokens the bucket may hold.
+    capacity: f64,
+    /// (Available tokens, time of last refill). Tokens may go negative, which is debt owed by
+    /// whichever callers are currently asleep.
+    state: Mutex<(f64, Instant)>,
+}
+impl TokenBucket {
+    /// Creates a full bucket for `rate` which may deliver `burst` worth of IO at once.
+    pub fn new(rate: Rate, burst: Duration) -> TokenBucket {
+        let capacity: f64 = rate.per_second() * burst.as_secs_f64();
+        TokenBucket { rate, capacity, state: Mutex::new((capacity, Instant::now())) }
+    }
+
+    /// Reserves tokens for an operation of `bytes` and returns how long the caller must wait
+    /// before issuing it.
+    pub fn reserve(&self, bytes: u64) -> Duration {
+        let mut state = self.state.lock().expect("token bucket mutex poisoned");
+        let (tokens, refilled) = *state;
+
+        let now: Instant = Instant::now();
+        let refill: f64 = now.duration_since(refilled).as_secs_f64() * self.rate.per_second();
+        let tokens: f64 = (tokens + refill).min(self.capacity) - self.rate.cost(bytes);
+        *state = (tokens, now);
+
+        if tokens < 0.0 { Duration::from_secs_f64(-tokens / self.rate.per_second()) }
+        else            { Duration::ZERO }
+    }
+
+    /// Blocks until an operation of `bytes` may be issued.
+    pub fn acquire(&self, bytes: u64) {
+        let wait: Duration = self.reserve(bytes);
+        if !wait.is_zero() { std::thread::sleep(wait); }
+    }
+}
+
+/// Rate limits applied to a phase. Either limit is optional; when both are present an operation
+/// must satisfy both.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    /// Limit on all threads combined.
+    pub global: Option<Rate>,
+    /// Limit on each thread individually.
+    pub thread: Option<Rate>,
+    /// How much IO, expressed as time at the limiting rate, may be issued at once.
+    pub burst: Duration,
+}
+impl Limits {
+    pub fn is_limited(&self) -> bool { self.global.is_some() || self.thread.is_some() }
+
+    /// Whether either limit is in bytes per second, which operations that move no data, such as
+    /// those of the metadata benchmark, would never be held to.
+    pub fn limits_bytes(&self) -> bool {
+        [self.global, self.thread].iter().flatten().any(|rate| matches!(rate, Rate::Bytes(_)))
+    }
+}
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |r: Option<Rate>| r.map_or("unlimited".to_string(), |r| r.to_string());
+        write!(f, "global {}, thread {}", show(self.global), show(self.thread))
+    }
+}
+
+/// The throttle used by a single thread: its own bucket plus a bucket shared with all other
+/// threads of the phase.
+#[derive(Debug)]
+pub struct Throttle {
+    global: Option<Arc<TokenBucket>>,
+    thread: Option<TokenBucket>,
+}
+impl Throttle {
+    /// Creates the bucket shared by all threads of a phase, if there is a global limit.
+    pub fn shared(limits: &Limits) -> Option<Arc<TokenBucket>> {
+        limits.global.map(|rate| Arc::new(TokenBucket::new(rate, limits.burst)))
+    }
+
+    pub fn new(limits: &Limits, global: Option<Arc<TokenBucket>>) -> Throttle {
+        Throttle {
+            global,
+            thread: limits.thread.map(|rate| TokenBucket::new(rate, limits.burst)),
+        }
+    }
+
+    /// Blocks until both the thread and global limits permit an operation of `bytes`.
+    pub fn acquire(&self, bytes: u64) {
+        // Reserve from both before sleeping so the two waits overlap rather than add.
+        let wait: Duration = [self.thread.as_ref(), self.global.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(|bucket| bucket.reserve(bytes))
+            .max()
+            .unwrap_or_default();
+        if !wait.is_zero() { std::thread::sleep(wait); }
+    }
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn rate_parsing() {
+        assert_eq!("1024".parse::<Rate>().unwrap(), Rate::Bytes(1024));
+        assert_eq!("100MiB/s".parse::<Rate>().unwrap(), Rate::Bytes(100 << 20));
+        assert_eq!("2G".parse::<Rate>().unwrap(), Rate::Bytes(2 << 30));
+        assert_eq!("500iops".parse::<Rate>().unwrap(), Rate::Operations(500));
+        assert!("0".parse::<Rate>().is_err());
+        assert!("fast".parse::<Rate>().is_err());
+        assert!("10 parsecs".parse::<Rate>().is_err());
+        assert!("17179869185G".parse::<Rate>().is_err());
+        assert_eq!("17179869183G".parse::<Rate>().unwrap(), Rate::Bytes(17179869183 << 30));
+
+        let limits = Limits { global: Some(Rate::Operations(500)), thread: None, burst: Duration::ZERO };
+        assert!(!limits.limits_bytes());
+        assert!(Limits { thread: Some(Rate::Bytes(1 << 20)), ..limits }.limits_bytes());
+    }
+
+    #[test]
+    fn bucket_paces_after_burst() {
+        // 1000 ops/s with a 10ms burst allows 10 operations immediately.
+        let bucket = TokenBucket::new(Rate::Operations(1000), Duration::from_millis(10));
+        (0..10).for_each(|_| assert!(bucket.reserve(4096) < Duration::from_millis(1)));
+
+        // Afterwards each operation is owed ~1ms more than the previous one.
+        let owed: Duration = (0..10).map(|_| bucket.reserve(4096)).last().unwrap();
+        assert!(owed >= Duration::from_millis(9), "owed {owed:?}");
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use super::{
+    affinity::Location,
+    constellation::{FileConstellation, HandleCache, Handles},
+    engine::Engine,
+    measure::{Tally, Window},
+    page::Page,
+    workload::Operation,
+    DATA_WORDS,
+    PAGE_BYTES,
+};
+
+
+/// First bytes of a binary trace.
+pub const MAGIC: &[u8; 8] = b"SMIOTRC1";
+
+/// Size of each record of a binary trace.
+pub const RECORD_BYTES: usize = 48;
+
+/// Records a thread keeps before adding them to the trace file.
+const BUFFERED: usize = 4096;
+
+/// # Overview
+/// A single IO issued by a benchmark, or to be issued by `replay`. Only reads, writes and syncs
+/// are traced; a sync covers the whole file and has no offset or length.
+///
+/// A binary trace is `MAGIC` followed by records of `RECORD_BYTES`, all little-endian:
+///
+/// ```txt
+///    bytes    field
+///    0..8     time      u64  nanoseconds from the start of the trace to the IO being issued
+///    8..16    latency   u64  nanoseconds from issue to completion
+///    16..24   file      u64  file id within the constellation
+///    24..32   offset    u64  bytes
+///    32..40   result    i64  bytes moved, or the negated errno of a failed IO
+///    40..44   length    u32  bytes
+///    44..46   thread    u16
+///    46       operation u8   0 read, 1 write, 2 sync
+///    47       reserved  u8   zero
+/// ```
+///
+/// Records are written in batches by each thread, so are in time order within a thread but not
+/// across threads; `load` sorts them.
+///
+/// Traces captured elsewhere may instead be text, one IO per line, with `#` starting a comment:
+///
+/// ```txt
+///    # time_us thread operation file offset length
+///    0         0      read      3    0      1048576
+///    150       1      write     0    4096   4096
+///    900       1      sync      0    0      0
+/// ```
+///
+/// Latency and result aren't known for such IO and are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub time_ns: u64,
+    pub latency_ns: u64,
+    pub file: u64,
+    pub offset: u64,
+    pub result: i64,
+    pub length: u32,
+    pub thread: u16,
+    pub operation: Operation,
+}
+impl Record {
+    pub fn encode(&self) -> [u8; RECORD_BYTES] {
+        let mut bytes = [0u8; RECORD_BYTES];
+        bytes[0..8].copy_from_slice(&self.time_ns.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.latency_ns.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.file.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.result.to_le_bytes());
+        bytes[40..44].copy_from_slice(&self.length.to_le_bytes());
+        bytes[44..46].copy_from_slice(&self.thread.to_le_bytes());
+        bytes[46] = match self.operation {
+            Operation::Read  => 0,
+            Operation::Write => 1,
+            Operation::Sync  => 2,
+            _ => unreachable!("only data operations are traced"),
+        };
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; RECORD_BYTES]) -> Result<Record> {
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().expect("slice is 8 bytes"));
+        Ok(Record {
+            time_ns: u64_at(0),
+            latency_ns: u64_at(8),
+            file: u64_at(16),
+            offset: u64_at(24),
+            result: u64_at(32) as i64,
+            length: u32::from_le_bytes(bytes[40..44].try_into().expect("slice is 4 bytes")),
+            thread: u16::from_le_bytes(bytes[44..46].try_into().expect("slice is 2 bytes")),
+            operation: match bytes[46] {
+                0 => Operation::Read,
+                1 => Operation::Write,
+                2 => Operation::Sync,
+                code => return Err(anyhow!("unknown operation code {code}")),
+            },
+        })
+    }
+}
+impl FromStr for Record {
+    type Err = anyhow::Error;
+
+    /// Parses a line of a text trace; see `Record`.
+    fn from_str(s: &str) -> Result<Record> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [time, thread, operation, file, offset, length] = fields[..] else {
+            return Err(anyhow!("expected 'time_us thread operation file offset length' but found {} fields", fields.len()));
+        };
+        let number = |name: &str, value: &str| -> Result<u64> {
+            value.parse().map_err(|_| anyhow!("invalid {name} '{value}'"))
+        };
+        Ok(Record {
+            time_ns: number("time", time)?.saturating_mul(1000),
+            latency_ns: 0,
+            file: number("file", file)?,
+            offset: number("offset", offset)?,
+            result: 0,
+            length: u32::try_from(number("length", length)?).map_err(|_| anyhow!("length {length} is too large"))?,
+            thread: u16::try_from(number("thread", thread)?).map_err(|_| anyhow!("thread {thread} is too large"))?,
+            operation: match operation {
+                "read"  => Operation::Read,
+                "write" => Operation::Write,
+                "sync"  => Operation::Sync,
+                _ => return Err(anyhow!("invalid operation '{operation}'; expected read, write or sync")),
+            },
+        })
+    }
+}
+
+/// Writes a binary trace of `records`.
+pub fn save(path: &Path, records: &[Record]) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path).map_err(|e| anyhow!("unable to create trace {}: {e}", path.display()))?);
+    out.write_all(MAGIC)?;
+    records.iter().try_for_each(|record| out.write_all(&record.encode()))?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Reads a binary or text trace, in time order.
+pub fn load(path: &Path) -> Result<Vec<Record>> {
+    let bytes: Vec<u8> = std::fs::read(path).map_err(|e| anyhow!("unable to read trace {}: {e}", path.display()))?;
+    let mut records: Vec<Record> = match bytes.strip_prefix(MAGIC) {
+        Some(body) if body.len() % RECORD_BYTES == 0 => body.chunks_exact(RECORD_BYTES)
+            .enumerate()
+            .map(|(i, chunk)| Record::decode(chunk.try_into().expect("chunks are whole records"))
+                                     .with_context(|| format!("record {i} of trace {}", path.display())))
+            .collect::<Result<_>>()?,
+        Some(_) => return Err(anyhow!("trace {} ends part way through a record", path.display())),
+        None => std::str::from_utf8(&bytes)
+            .map_err(|_| anyhow!("trace {} is neither a binary nor a text trace", path.display()))?
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.split('#').next().unwrap_or_default().trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(i, line)| line.parse().with_context(|| format!("line {} of trace {}", i + 1, path.display())))
+            .collect::<Result<_>>()?,
+    };
+    records.sort_by_key(|record| record.time_ns);
+    Ok(records)
+}
+
+/// Appends the IO of every thread of a run to a binary trace. Times are measured from when the
+/// tracer was created.
+pub struct Tracer {
+    started: Instant,
+    out: Mutex<BufWriter<File>>,
+}
+impl Tracer {
+    pub fn create(path: &Path) -> Result<Tracer> {
+        let mut out = BufWriter::new(File::create(path).map_err(|e| anyhow!("unable to create trace {}: {e}", path.display()))?);
+        out.write_all(MAGIC)?;
+        Ok(Tracer { started: Instant::now(), out: Mutex::new(out) })
+    }
+
+    /// A buffer for the records of `thread`. Its records are added to the trace when it fills
+    /// and when it is dropped.
+    pub fn thread(&self, thread: usize) -> ThreadTrace<'_> {
+        ThreadTrace { tracer: self, thread: thread as u16, records: Vec::with_capacity(BUFFERED) }
+    }
+
+    /// Writes out everything added so far.
+    pub fn flush(&self) -> Result<()> {
+        Ok(self.out.lock().expect("trace writers don't panic").flush()?)
+    }
+
+    fn write(&self, records: &[Record]) -> std::io::Result<()> {
+        let mut out = self.out.lock().expect("trace writers don't panic");
+        records.iter().try_for_each(|record| out.write_all(&record.encode()))
+    }
+}
+
+/// The records of one thread which are yet to be added to the trace.
+pub struct ThreadTrace<'a> {
+    tracer: &'a Tracer,
+    thread: u16,
+    records: Vec<Record>,
+}
+impl ThreadTrace<'_> {
+    /// Records an IO issued at `issued` which has just completed with `result`.
+    pub fn record(&mut self, operation: Operation, file: u64, offset: u64, length: usize, issued: Instant, result: i64) {
+        self.records.push(Record {
+            time_ns: issued.saturating_duration_since(self.tracer.started).as_nanos() as u64,
+            latency_ns: issued.elapsed().as_nanos() as u64,
+            file,
+            offset,
+            result,
+            length: length as u32,
+            thread: self.thread,
+            operation,
+        });
+        if self.records.len() >= BUFFERED { self.flush(); }
+    }
+
+    fn flush(&mut self) {
+        if let Err(e) = self.tracer.write(&self.records) {
+            warn!("Unable to add {} records to the trace: {e}", self.records.len());
+        }
+        self.records.clear();
+    }
+}
+impl Drop for ThreadTrace<'_> {
+    fn drop(&mut self) { self.flush(); }
+}
+
+/// When `replay` issues each IO.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// At the time it was issued in the trace, or as soon as the previous IO of its thread
+    /// completes if that is later.
+    #[default]
+    Original,
+    /// As soon as the previous IO of its thread completes.
+    Fast,
+}
+impl Timing {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Timing::Original => "original",
+            Timing::Fast     => "fast",
+        }
+    }
+}
+impl FromStr for Timing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Timing> {
+        match s.trim() {
+            "original" => Ok(Timing::Original),
+            "fast"     => Ok(Timing::Fast),
+            _ => Err(anyhow!("invalid timing '{s}'; expected 'original' or 'fast'")),
+        }
+    }
+}
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// Number of threads which issue the IO of a trace; one per thread id it holds.
+pub fn threads(records: &[Record]) -> usize {
+    records.iter().map(|record| record.thread).collect::<std::collections::BTreeSet<u16>>().len()
+}
+
+/// Checks that every IO of a trace falls within the files of the constellation.
+pub fn validate(files: &FileConstellation, records: &[Record]) -> Result<()> {
+    let count: u64 = files.count();
+    let beyond = |r: &Record| r.offset.checked_add(r.length as u64).is_none_or(|end| end > files.size(r.file));
+    match records.iter().enumerate().find(|(_, r)| r.file >= count || beyond(r)) {
+        Some((i, r)) if r.file >= count => Err(anyhow!("IO {i} is of file {} but the constellation has {count} files", r.file)),
+        Some((i, r)) => Err(anyhow!("IO {i} covers {} bytes from byte {} of file {} which holds {}", r.length, r.offset, r.file, files.size(r.file))),
+        None => Ok(()),
+    }
+}
+
+/// # Overview
+/// Re-issues the IO of a trace against a constellation, one thread per thread of the trace,
+/// each joining `window`, and returns what each thread measured. Writes are filled with pages for
+/// `seed`. Failed IO is logged and left
+/// out of the tallies.
+///
+/// Reads are not checked. Writes write the bytes which the constellation's pages hold after
+/// `create`, so a replay leaves the constellation as valid as it found it unless it writes part
+/// of a page which was mutated since.
+pub fn replay(files: &FileConstellation, records: &[Record], timing: Timing, engine: Engine, seed: u64, window: &Window) -> Result<Vec<Tally>> {
+    validate(files, records)?;
+    let mut by_thread: BTreeMap<u16, Vec<&Record>> = BTreeMap::new();
+    records.iter().for_each(|record| by_thread.entry(record.thread).or_default().push(record));
+    let handles: Handles = Handles::cached_within_limit(by_thread.len())?;
+    let started: Instant = Instant::now();
+
+    let tallies: Vec<Tally> = std::thread::scope(|scope| {
+        let workers: Vec<_> = by_thread.values().enumerate().map(|(index, records)| {
+            scope.spawn(move || {
+                let mut handles: HandleCache = HandleCache::new(files, handles);
+                let mut tallies: Vec<Tally> = [Operation::Read, Operation::Write, Operation::Sync]
+                    .map(|operation| Tally::new(index, Location::default(), operation))
+                    .into();
+                let mut buffer: Vec<u8> = Vec::new();
+                window.join(index);
+                for record in records {
+                    // The buffer is filled before waiting so that doing so doesn't delay the IO.
+                    let length: usize = record.length as usize;
+                    let write: bool = record.operation != Operation::Read;
+                    if record.operation == Operation::Write {
+                        buffer = written_bytes(seed, record.file, record.offset, length);
+                    } else {
+                        buffer.resize(length, 0);
+                    }
+                    if timing == Timing::Original {
+                        let due: Instant = started + Duration::from_nanos(record.time_ns);
+                        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                    }
+                    let file: &File = match handles.get(record.file, true, write) {
+                        Ok(file) => file,
+                        Err(e) => { warn!("Unable to open file {} to replay: {e}", record.file); continue; },
+                    };
+                    let issued: Instant = Instant::now();
+                    let result: std::io::Result<usize> = match record.operation {
+                        Operation::Read  => engine.read_all_at(file, &mut buffer, record.offset, PAGE_BYTES),
+                        Operation::Write => engine.write_all_at(file, &buffer, record.offset, PAGE_BYTES).map(|_| length),
+                        _                => file.sync_data().map(|_| 0),
+                    };
+                    match result {
+                        Ok(bytes) => tallies.iter_mut()
+                                            .find(|t| t.operation == record.operation)
+                                            .expect("a tally exists for each traced operation")
+                                            .record(bytes as u64, issued),
+                        Err(e) => warn!("Replayed {} of file {} at offset {} failed: {e}", record.operation, record.file, record.offset),
+                    }
+                }
+                tallies
+            })
+        }).collect();
+        workers.into_iter().flat_map(|worker| worker.join().expect("replay workers don't panic")).collect()
+    });
+    Ok(tallies)
+}
+
+/// The bytes a constellation created with `seed` holds at `offset` of `file`. The bytes must lie
+/// within the file, as `validate` checks.
+fn written_bytes(seed: u64, file: u64, offset: u64, length: usize) -> Vec<u8> {
+    let end: u64 = offset.checked_add(length as u64).expect("validated IO ends within its file");
+    let (first, last) = (offset / PAGE_BYTES as u64, end.div_ceil(PAGE_BYTES as u64));
+    let pages: Vec<u8> = (first..last).flat_map(|page| bytemuck::bytes_of(&Page::<DATA_WORDS>::new(seed, file, page)).to_vec())
+                                       .collect();
+    let start: usize = (offset - first * PAGE_BYTES as u64) as usize;
+    pages[start..start + length].to_vec()
+}
+
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn traces_round_trip_and_replay() {
+        use crate::{constellation::FileOptions, measure::WarmUp, sizes::FileSizes};
+        use tempfile::tempdir;
+
+        let root = tempdir().expect("crate should be able to create temporary directories");
+        let traced = root.path().join("trace.bin");
+        let tracer = Tracer::create(&traced).unwrap();
+        let issued: Instant = Instant::now();
+        {
+            let mut thread = tracer.thread(1);
+            thread.record(Operation::Write, 1, 4096, 8192, issued, 8192);
+            thread.record(Operation::Read, 0, 100, 50, issued, 50);
+            tracer.thread(0).record(Operation::Sync, 1, 0, 0, issued, 0);
+        }
+        tracer.flush().unwrap();
+        let records: Vec<Record> = load(&traced).unwrap();
+        assert_eq!(records.len(), 3);
+        let write: &Record = records.iter().find(|r| r.operation == Operation::Write).unwrap();
+        assert_eq!((write.thread, write.file, write.offset, write.length, write.result), (1, 1, 4096, 8192, 8192));
+        assert_eq!(threads(&records), 2);
+
+        let text = root.path().join("trace.txt");
+        std::fs::write(&text, "# time_us thread operation file offset length\n20 0 read 0 0 4096\n10 1 write 1 6000 100 # partial pages\n").unwrap();
+        let parsed: Vec<Record> = load(&text).unwrap();
+        assert_eq!((parsed[0].time_ns, parsed[0].operation), (10_000, Operation::Write));
+        std::fs::write(&text, "10 1 trim 1 0 100\n").unwrap();
+        assert!(load(&text).is_err());
+
+        let files: FileConstellation = FileConstellation::new(
+            vec![root.path().to_path_buf()],
+            ("test_dir".to_string(), 1),
+            ("test_file".to_string(), 2),
+            FileSizes::fixed(4 * PAGE_BYTES as u64).unwrap(),
+            FileOptions { directo_io: false, ..Default::default() },
+            true
+        ).expect("created directories and files");
+        let window = Window::new(threads(&parsed), WarmUp::None, Duration::ZERO);
+        let tallies: Vec<Tally> = replay(&files, &parsed, Timing::Fast, Engine::Posix, 7, &window).unwrap();
+        let moved = |operation: Operation| tallies.iter().filter(|t| t.operation == operation).map(|t| t.bytes).sum::<u64>();
+        assert_eq!((moved(Operation::Read), moved(Operation::Write)), (4096, 100));
+        assert_eq!(written_bytes(7, 1, 100, 10), bytemuck::bytes_of(&Page::<DATA_WORDS>::new(7, 1, 0))[100..110]);
+
+        let beyond: Record = Record { offset: 4 * PAGE_BYTES as u64, ..parsed[0] };
+        assert!(replay(&files, &[beyond], Timing::Fast, Engine::Posix, 7, &window).is_err());
+        let overflowing: Record = Record { offset: u64::MAX - 10, ..parsed[0] };
+        assert!(replay(&files, &[overflowing], Timing::Fast, Engine::Posix, 7, &window).is_err());
+    }
+}
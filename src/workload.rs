@@ -0,0 +1,416 @@
+use std::{fmt, str::FromStr};
+use rand_xoshiro::{
+    Xoroshiro128PlusPlus,
+    rand_core::{RngCore, SeedableRng},
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+use super::{placement::splitmix64, queue::work::DIter, PAGE_BYTES};
+
+
+/// The kind of IO a unit of work performs. Data operations move bytes to or from a file, or
+/// flush them to storage; the remainder only touch file system metadata, see `metadata`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Operation {
+    Read,
+    Write,
+    Sync,
+    Create,
+    Stat,
+    Open,
+    Rename,
+    Unlink,
+}
+impl Operation {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Operation::Read   => "read",
+            Operation::Write  => "write",
+            Operation::Sync   => "sync",
+            Operation::Create => "create",
+            Operation::Stat   => "stat",
+            Operation::Open   => "open",
+            Operation::Rename => "rename",
+            Operation::Unlink => "unlink",
+        }
+    }
+
+    /// Whether the operation only touches file system metadata.
+    pub fn is_metadata(&self) -> bool { !matches!(self, Operation::Read | Operation::Write | Operation::Sync) }
+}
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// # Overview
+/// A deterministic mix of reads and writes. Whether a unit of work is a read or a write is
+/// derived from a seed and the unit's iteration, as yielded by `DIter`, so the same seed and
+/// access pattern always produce the same sequence of operations regardless of which thread
+/// executes them.
+///
+/// # Examples
+/// ```
+/// # use super_massive_io::workload::{Mix, Operation};
+/// let mix: Mix = "70/30".parse::<Mix>().unwrap().with_seed(0xD7D6D5D4D3D2D1D0);
+///
+/// let writes: usize = (0..10_000).filter(|i| mix.operation(*i) == Operation::Write).count();
+/// assert!((2_800..3_200).contains(&writes));
+///
+/// // Deterministic for a given seed and iteration.
+/// assert_eq!(mix.operation(42), mix.operation(42));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Mix {
+    /// Percentage, [0, 100], of units which are reads.
+    read_percent: u8,
+    seed: u64,
+}
+impl Mix {
+    pub fn new(read_percent: u8, seed: u64) -> Result<Mix> {
+        if read_percent > 100 {
+            return Err(anyhow!("invalid read percentage {read_percent}; must be at most 100"));
+        }
+        Ok(Mix { read_percent, seed })
+    }
+
+    /// Returns a copy of this mix which derives operations from `seed`.
+    pub fn with_seed(self, seed: u64) -> Mix { Mix { seed, ..self } }
+
+    pub fn read_percent(&self) -> u8 { self.read_percent }
+
+    /// The operation performed by the unit of work at `iteration`.
+    pub fn operation(&self, iteration: u64) -> Operation {
+        let roll: u64 = Xoroshiro128PlusPlus::seed_from_u64(self.seed ^ iteration).next_u64() % 100;
+        if roll < self.read_percent as u64 { Operation::Read }
+        else                               { Operation::Write }
+    }
+}
+impl FromStr for Mix {
+    type Err = anyhow::Error;
+
+    /// Parses a `read/write` ratio such as `70/30`, or just the read percentage. The seed is
+    /// zero; see `with_seed`.
+    fn from_str(s: &str) -> Result<Mix> {
+        let invalid = || anyhow!("invalid mix '{s}'; expected a read/write ratio such as '70/30'");
+        let (read, write): (u8, Option<u8>) = match s.split_once('/') {
+            Some((r, w)) => (r.trim().parse().map_err(|_| invalid())?,
+                             Some(w.trim().parse().map_err(|_| invalid())?)),
+            None         => (s.trim().parse().map_err(|_| invalid())?, None),
+        };
+        if write.is_some_and(|w| read as u16 + w as u16 != 100) {
+            return Err(anyhow!("invalid mix '{s}'; read and write percentages must sum to 100"));
+        }
+        Mix::new(read, 0)
+    }
+}
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.read_percent, 100 - self.read_percent)
+    }
+}
+
+/// The order in which the chapters of a constellation are handed to workers. Each pass over the
+/// constellation visits every chapter exactly once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Access {
+    /// Chapters in order, file by file.
+    #[default]
+    Sequential,
+    /// Chapters in a seeded pseudo-random order which differs on every pass.
+    Random,
+}
+impl Access {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Access::Sequential => "sequential",
+            Access::Random     => "random",
+        }
+    }
+
+    /// A queue over `chapters` chapters which ends after a single pass or, if `repeat`, continues
+    /// pass after pass. A queue over no chapters yields nothing.
+    pub fn queue(&self, chapters: u64, seed: u64, repeat: bool) -> DIter {
+        if chapters == 0 { return DIter::exhausted(); }
+        match self {
+            Access::Sequential => {
+                let iterations: u64 = if repeat { u64::MAX } else { chapters };
+                DIter::new(0, chapters, iterations, move |_l, _current, _u, iteration| Some(iteration % chapters))
+            },
+            Access::Random => {
+                // A permutation of a power of four at least as large as the chapter count. Values
+                // beyond the last chapter are skipped by the queue.
+                let bits: u32 = chapters.next_power_of_two().trailing_zeros().next_multiple_of(2).max(2);
+                let domain: u64 = 1 << bits;
+                let chapter = move |iteration: u64| permute(iteration % domain, bits, seed ^ (iteration / domain));
+                let first: u64 = (0..domain).find(|i| chapter(*i) < chapters).expect("the domain holds every chapter");
+                let iterations: u64 = if repeat { u64::MAX } else { domain };
+                DIter::new_with_state((chapter(first), first), 0..chapters, iterations, move |_l, _current, _u, iteration| {
+                    Some(chapter(iteration))
+                })
+            },
+        }
+    }
+}
+impl FromStr for Access {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Access> {
+        match s.trim() {
+            "sequential" => Ok(Access::Sequential),
+            "random"     => Ok(Access::Random),
+            _ => Err(anyhow!("invalid access pattern '{s}'; expected 'sequential' or 'random'")),
+        }
+    }
+}
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// A bijection on `[0, 2^bits)`, for even `bits`: a four round Feistel network keyed by `seed`.
+fn permute(value: u64, bits: u32, seed: u64) -> u64 {
+    let half: u32 = bits / 2;
+    let mask: u64 = (1 << half) - 1;
+    let (mut left, mut right): (u64, u64) = (value >> half, value & mask);
+    (0..4).for_each(|round| {
+        let mixed: u64 = splitmix64(right ^ seed.wrapping_add(round)) & mask;
+        (left, right) = (right, left ^ mixed);
+    });
+    (left << half) | right
+}
+
+/// When written data is forced to stable storage. Unforced writes may remain in the page cache,
+/// so their latency is that of a memory copy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Durability {
+    /// Writes are left to the operating system.
+    #[default]
+    None,
+    /// Every write is followed by `fdatasync`, which is measured as part of the write.
+    EveryWrite,
+    /// Every file is synced once the phase has finished. This is not measured.
+    EndOfPhase,
+}
+impl Durability {
+    pub fn to_str(&self) -> &str {
+        match self {
+            Durability::None       => "none",
+            Durability::EveryWrite => "every-write",
+            Durability::EndOfPhase => "end-of-phase",
+        }
+    }
+}
+impl FromStr for Durability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Durability> {
+        match s.trim() {
+            "none"         => Ok(Durability::None),
+            "every-write"  => Ok(Durability::EveryWrite),
+            "end-of-phase" => Ok(Durability::EndOfPhase),
+            _ => Err(anyhow!("invalid durability '{s}'; expected 'none', 'every-write' or 'end-of-phase'")),
+        }
+    }
+}
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// A contiguous range of bytes within a single file. This is the unit of work handed to a
+/// worker; it is at most a chapter long and always begins on a page boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Extent {
+    pub file: u64,
+    /// Byte offset of the extent within the file.
+    pub offset: u64,
+    /// Length in bytes. Only the last extent of a file may be shorter than a chapter and its
+    /// last page may be truncated by the end of the file.
+    pub length: u64,
+}
+impl Extent {
+    /// Index, within the file, of the first page of the extent.
+    pub fn first_page(&self) -> u64 { self.offset / PAGE_BYTES as u64 }
+
+    /// Number of pages, including a truncated final page, the extent touches.
+    pub fn pages(&self) -> u64 { self.length.div_ceil(PAGE_BYTES as u64) }
+
+    /// Number of bytes of the extent's `page`th page (relative to the extent) which lie in the
+    /// extent.
+    pub fn page_bytes(&self, page: u64) -> usize {
+        self.length.saturating_sub(page * PAGE_BYTES as u64).min(PAGE_BYTES as u64) as usize
+    }
+}
+
+/// # Overview
+/// Maps chapter indices, as yielded by a `DIter`, onto the files of a constellation. Every file
+/// is divided into chapters from its start; the last chapter of a file holds whatever remains
+/// when the file size is not a multiple of the chapter size. Files may differ in size, so the
+/// number of chapters differs between files. Chapters are numbered file by file:
+///
+/// ```txt
+///                 chapter 0   chapter 1   chapter 2 (partial)
+///    File 0      | 0         | 1         | 2    |
+///    File 1      | 3    |
+///    File 2      | 4         | 5         | 6         | 7 |
+/// ```
+///
+/// Iterating over `0..count()` therefore covers every byte of every file exactly once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChapterMap {
+    /// Size of each file in bytes.
+    sizes: Vec<u64>,
+    /// Index of the first chapter of each file, followed by the total number of chapters.
+    starts: Vec<u64>,
+    /// Index of the first page of each file, followed by the total number of pages, were the
+    /// files laid end to end.
+    page_starts: Vec<u64>,
+    chapter_bytes: u64,
+}
+impl ChapterMap {
+    /// Maps files of the given sizes, in order of file id, onto chapters of `chapter_bytes`.
+    pub fn new(sizes: impl IntoIterator<Item = u64>, chapter_bytes: u64) -> Result<ChapterMap> {
+        if chapter_bytes == 0 || !chapter_bytes.is_multiple_of(PAGE_BYTES as u64) {
+            return Err(anyhow!("chapter size {chapter_bytes} must be a non-zero multiple of the page size {PAGE_BYTES}"));
+        }
+        let sizes: Vec<u64> = sizes.into_iter().collect();
+        let starts_of = |unit: u64| -> Vec<u64> {
+            std::iter::once(0).chain(sizes.iter().scan(0, |start, size| {
+                *start += size.div_ceil(unit);
+                Some(*start)
+            })).collect()
+        };
+        let (starts, page_starts) = (starts_of(chapter_bytes), starts_of(PAGE_BYTES as u64));
+        Ok(ChapterMap { sizes, starts, page_starts, chapter_bytes })
+    }
+
+    /// Number of files mapped.
+    pub fn files(&self) -> u64 { self.sizes.len() as u64 }
+
+    /// Number of chapters in file `file`.
+    pub fn chapters_of(&self, file: u64) -> u64 {
+        self.starts[file as usize + 1] - self.starts[file as usize]
+    }
+
+    /// Number of chapters across all files.
+    pub fn count(&self) -> u64 { *self.starts.last().expect("starts holds at least the total") }
+
+    /// Number of bytes across all files.
+    pub fn bytes(&self) -> u64 { self.sizes.iter().sum() }
+
+    /// The extent covered by chapter `index`, or `None` if `index` is out of range.
+    pub fn extent(&self, index: u64) -> Option<Extent> {
+        if index >= self.count() { return None; }
+        // The last file whose first chapter is at or before `index`; empty files are skipped.
+        let file: usize = self.starts.partition_point(|start| *start <= index) - 1;
+        let offset: u64 = (index - self.starts[file]) * self.chapter_bytes;
+        Some(Extent { file: file as u64, offset, length: self.chapter_bytes.min(self.sizes[file] - offset) })
+    }
+
+    /// The extents covering `length` bytes from `offset` of the files laid end to end, page by
+    /// page, as though they were one device. Offsets beyond the last page wrap around to the
+    /// first, and the range is widened to whole pages. Each extent lies within one file and is
+    /// at most a chapter long.
+    pub fn extents_at(&self, offset: u64, length: u64) -> Vec<Extent> {
+        let page_bytes: u64 = PAGE_BYTES as u64;
+        let pages: u64 = *self.page_starts.last().expect("page_starts holds at least the total");
+        if pages == 0 || length == 0 { return Vec::new(); }
+
+        let mut page: u64 = (offset / page_bytes) % pages;
+        let mut remaining: u64 = (offset % page_bytes).saturating_add(length).div_ceil(page_bytes).min(pages);
+        let mut extents: Vec<Extent> = Vec::new();
+        while remaining > 0 {
+            let file: usize = self.page_starts.partition_point(|start| *start <= page) - 1;
+            let first: u64 = page - self.page_starts[file];
+            let taken: u64 = remaining.min(self.page_starts[file + 1] - page)
+                                      .min(self.chapter_bytes / page_bytes);
+            let offset: u64 = first * page_bytes;
+            extents.push(Extent { file: file as u64, offset, length: (taken * page_bytes).min(self.sizes[file] - offset) });
+            page = (page + taken) % pages;
+            remaining -= taken;
+        }
+        extents
+    }
+}
+
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn mix_parsing() {
+        assert_eq!("70/30".parse::<Mix>().unwrap().read_percent(), 70);
+        assert_eq!("100".parse::<Mix>().unwrap().read_percent(), 100);
+        assert!("70/40".parse::<Mix>().is_err());
+        assert!("101".parse::<Mix>().is_err());
+        assert!("most".parse::<Mix>().is_err());
+
+        // The extremes never produce the other operation.
+        let reads = Mix::new(100, 1).unwrap();
+        let writes = Mix::new(0, 1).unwrap();
+        assert!((0..1024).all(|i| reads.operation(i) == Operation::Read));
+        assert!((0..1024).all(|i| writes.operation(i) == Operation::Write));
+    }
+
+    #[test]
+    fn chapters_cover_every_byte_once() {
+        const CHAPTER: u64 = 4 * PAGE_BYTES as u64;
+        // Multiples of the chapter size, a partial chapter, and a partial page.
+        let uniform = [CHAPTER, 3 * CHAPTER, CHAPTER + PAGE_BYTES as u64, 2 * CHAPTER + 64, 64].map(|size| vec![size; 3]);
+        // Files of differing sizes, including an empty one.
+        let mixed = vec![vec![64, 3 * CHAPTER, 0, CHAPTER + 64, CHAPTER]];
+        for sizes in uniform.into_iter().chain(mixed) {
+            let map = ChapterMap::new(sizes.iter().copied(), CHAPTER).unwrap();
+            let extents: Vec<Extent> = (0..map.count()).map(|i| map.extent(i).unwrap()).collect();
+            assert!(map.extent(map.count()).is_none());
+
+            for (file, file_size) in sizes.iter().enumerate() {
+                let mut next: u64 = 0;
+                extents.iter().filter(|e| e.file == file as u64).for_each(|e| {
+                    assert_eq!(e.offset, next, "extents are contiguous and disjoint");
+                    assert!(e.length > 0 && e.length <= CHAPTER);
+                    next += e.length;
+                });
+                assert_eq!(next, *file_size, "file {file} of {file_size} bytes is fully covered");
+                assert_eq!(map.chapters_of(file as u64), file_size.div_ceil(CHAPTER));
+            }
+        }
+
+        let last = ChapterMap::new([CHAPTER + 64], CHAPTER).unwrap().extent(1).unwrap();
+        assert_eq!((last.first_page(), last.pages(), last.page_bytes(0)), (4, 1, 64));
+        assert!(ChapterMap::new([CHAPTER], 100).is_err());
+    }
+
+    #[test]
+    fn access_patterns_visit_every_chapter_once_per_pass() {
+        for chapters in [1, 2, 5, 64, 1000] {
+            let sequential: Vec<u64> = Access::Sequential.queue(chapters, 7, false).map(|(c, _)| c).collect();
+            assert_eq!(sequential, (0..chapters).collect::<Vec<u64>>());
+
+            let mut random: Vec<u64> = Access::Random.queue(chapters, 7, false).map(|(c, _)| c).collect();
+            if chapters == 1000 { assert_ne!(random, sequential, "random order is shuffled"); }
+            random.sort();
+            assert_eq!(random, sequential);
+        }
+
+        // Repeated passes are each complete but in a different order.
+        let passes: Vec<u64> = Access::Random.queue(100, 3, true).take(200).map(|(c, _)| c).collect();
+        let (first, second): (&[u64], &[u64]) = passes.split_at(100);
+        assert_ne!(first, second);
+        let mut second: Vec<u64> = second.to_vec();
+        second.sort();
+        assert_eq!(second, (0..100).collect::<Vec<u64>>());
+
+        // Nothing to do is nothing to yield, however long a timed phase may repeat.
+        for access in [Access::Sequential, Access::Random] {
+            assert_eq!(access.queue(0, 7, true).count(), 0);
+        }
+
+        assert!("strided".parse::<Access>().is_err());
+        assert_eq!("every-write".parse::<Durability>().unwrap(), Durability::EveryWrite);
+    }
+}